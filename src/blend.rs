@@ -0,0 +1,213 @@
+use palette::{Blend, Rgb};
+
+use render::PreRgba;
+
+/// How the colors of a layer are mixed with the colors behind it before the result is composited
+/// using source-over.
+///
+/// The modes are those of the Compositing and Blending specification, but they operate on linear
+/// RGB instead of sRGB.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Uses the color of the layer.
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Darken,
+    Lighten,
+    ColorDodge,
+    ColorBurn,
+    HardLight,
+    SoftLight,
+    Difference,
+    Exclusion,
+    /// Uses the hue of the layer with the saturation and luminosity of the backdrop.
+    Hue,
+    /// Uses the saturation of the layer with the hue and luminosity of the backdrop.
+    Saturation,
+    /// Uses the hue and saturation of the layer with the luminosity of the backdrop.
+    Color,
+    /// Uses the luminosity of the layer with the hue and saturation of the backdrop.
+    Luminosity,
+}
+
+impl Default for BlendMode {
+    fn default() -> BlendMode {
+        BlendMode::Normal
+    }
+}
+
+impl BlendMode {
+    /// Returns the mode with the given keyword of the `mix-blend-mode` property.
+    pub fn from_name(name: &str) -> Option<BlendMode> {
+        let mode = match name {
+            "normal" => BlendMode::Normal,
+            "multiply" => BlendMode::Multiply,
+            "screen" => BlendMode::Screen,
+            "overlay" => BlendMode::Overlay,
+            "darken" => BlendMode::Darken,
+            "lighten" => BlendMode::Lighten,
+            "color-dodge" => BlendMode::ColorDodge,
+            "color-burn" => BlendMode::ColorBurn,
+            "hard-light" => BlendMode::HardLight,
+            "soft-light" => BlendMode::SoftLight,
+            "difference" => BlendMode::Difference,
+            "exclusion" => BlendMode::Exclusion,
+            "hue" => BlendMode::Hue,
+            "saturation" => BlendMode::Saturation,
+            "color" => BlendMode::Color,
+            "luminosity" => BlendMode::Luminosity,
+            _ => return None,
+        };
+        Some(mode)
+    }
+
+    /// Blends `source` with `backdrop` and composites the result onto `backdrop`.
+    pub fn blend(self, source: PreRgba, backdrop: PreRgba) -> PreRgba {
+        // where either color is transparent there is nothing to mix
+        if source.alpha == 0. || backdrop.alpha == 0. {
+            return source.over(backdrop);
+        }
+        match self {
+            BlendMode::Normal => source.over(backdrop),
+            BlendMode::Multiply => source.multiply(backdrop),
+            BlendMode::Screen => source.screen(backdrop),
+            BlendMode::Overlay => source.overlay(backdrop),
+            BlendMode::Darken => source.darken(backdrop),
+            BlendMode::Lighten => source.lighten(backdrop),
+            BlendMode::ColorDodge => source.dodge(backdrop),
+            BlendMode::ColorBurn => source.burn(backdrop),
+            BlendMode::HardLight => source.hard_light(backdrop),
+            BlendMode::SoftLight => source.soft_light(backdrop),
+            BlendMode::Difference => source.difference(backdrop),
+            BlendMode::Exclusion => source.exclusion(backdrop),
+            _ => self.blend_non_separable(source, backdrop),
+        }
+    }
+
+    fn blend_non_separable(self, source: PreRgba, backdrop: PreRgba) -> PreRgba {
+        let (alpha_s, alpha_b) = (source.alpha, backdrop.alpha);
+        let unpremultiply = |color: Rgb<f32>, alpha: f32| {
+            [color.red / alpha, color.green / alpha, color.blue / alpha]
+        };
+        let s = unpremultiply(source.color, alpha_s);
+        let b = unpremultiply(backdrop.color, alpha_b);
+        let mixed = match self {
+            BlendMode::Hue => set_luminosity(set_saturation(s, saturation(b)), luminosity(b)),
+            BlendMode::Saturation => {
+                set_luminosity(set_saturation(b, saturation(s)), luminosity(b))
+            }
+            BlendMode::Color => set_luminosity(s, luminosity(b)),
+            _ => set_luminosity(b, luminosity(s)),
+        };
+        // `cs (1 - ab) + cb (1 - as) + as ab B(Cb, Cs)` with premultiplied `cs` and `cb`
+        let channel = |index: usize, s: f32, b: f32| {
+            s * (1. - alpha_b) + b * (1. - alpha_s) + alpha_s * alpha_b * mixed[index]
+        };
+        let mut result = source;
+        result.color = Rgb::new(
+            channel(0, source.color.red, backdrop.color.red),
+            channel(1, source.color.green, backdrop.color.green),
+            channel(2, source.color.blue, backdrop.color.blue),
+        );
+        result.alpha = alpha_s + alpha_b - alpha_s * alpha_b;
+        result
+    }
+}
+
+fn luminosity(color: [f32; 3]) -> f32 {
+    0.3 * color[0] + 0.59 * color[1] + 0.11 * color[2]
+}
+
+fn saturation(color: [f32; 3]) -> f32 {
+    max(color) - min(color)
+}
+
+fn min(color: [f32; 3]) -> f32 {
+    color[0].min(color[1]).min(color[2])
+}
+
+fn max(color: [f32; 3]) -> f32 {
+    color[0].max(color[1]).max(color[2])
+}
+
+/// Shifts `color` to the luminosity `target` and brings it back into gamut, preserving its
+/// luminosity.
+fn set_luminosity(color: [f32; 3], target: f32) -> [f32; 3] {
+    let delta = target - luminosity(color);
+    let color = [color[0] + delta, color[1] + delta, color[2] + delta];
+    let l = luminosity(color);
+    let (low, high) = (min(color), max(color));
+    let mut result = color;
+    for channel in &mut result {
+        if low < 0. {
+            *channel = l + (*channel - l) * l / (l - low);
+        }
+        if high > 1. {
+            *channel = l + (*channel - l) * (1. - l) / (high - l);
+        }
+    }
+    result
+}
+
+/// Stretches the channels of `color` such that its saturation is `saturation` and its smallest
+/// channel is zero.
+fn set_saturation(color: [f32; 3], saturation: f32) -> [f32; 3] {
+    let (low, high) = (min(color), max(color));
+    if high <= low {
+        return [0.; 3];
+    }
+    let scale = saturation / (high - low);
+    [(color[0] - low) * scale, (color[1] - low) * scale, (color[2] - low) * scale]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use palette::Rgba;
+
+    const EPS: f32 = 1.0e-5;
+
+    fn color(red: f32, green: f32, blue: f32, alpha: f32) -> PreRgba {
+        PreRgba::from(Rgba::new(red, green, blue, alpha))
+    }
+
+    #[test]
+    fn test_separable() {
+        let source = color(0.5, 1., 0., 1.);
+        let backdrop = color(0.5, 0.5, 0.5, 1.);
+        let multiply = BlendMode::Multiply.blend(source, backdrop);
+        assert!((multiply.red - 0.25).abs() < EPS && (multiply.green - 0.5).abs() < EPS);
+        assert_eq!(multiply.blue, 0.);
+        let screen = BlendMode::Screen.blend(source, backdrop);
+        assert!((screen.red - 0.75).abs() < EPS && (screen.blue - 0.5).abs() < EPS);
+        let difference = BlendMode::Difference.blend(source, backdrop);
+        assert!(difference.red.abs() < EPS && (difference.green - 0.5).abs() < EPS);
+
+        // a translucent source only mixes in part of the blended color
+        let multiply = BlendMode::Multiply.blend(color(0., 0., 0., 0.5), backdrop);
+        assert!((multiply.red - 0.25).abs() < EPS && (multiply.alpha - 1.).abs() < EPS);
+        // and nothing is mixed over a transparent backdrop
+        let source = color(0.5, 1., 0., 0.5);
+        assert_eq!(BlendMode::Multiply.blend(source, color(0., 0., 0., 0.)), source);
+    }
+
+    #[test]
+    fn test_non_separable() {
+        let red = color(1., 0., 0., 1.);
+        let grey = color(0.5, 0.5, 0.5, 1.);
+        // the luminosity of grey with the hue and saturation of red is out of gamut
+        let result = BlendMode::Color.blend(red, grey);
+        let rgb = [result.red, result.green, result.blue];
+        assert!((luminosity(rgb) - 0.5).abs() < EPS);
+        assert!(rgb.iter().all(|&channel| channel >= -EPS && channel <= 1. + EPS));
+        assert!(rgb[0] > rgb[1] && (rgb[1] - rgb[2]).abs() < EPS);
+
+        // grey has no saturation and no hue
+        let result = BlendMode::Saturation.blend(grey, red);
+        assert!((result.red - result.green).abs() < EPS && (result.red - 0.3).abs() < EPS);
+        let result = BlendMode::Luminosity.blend(grey, grey);
+        assert!((result.red - 0.5).abs() < EPS && (result.alpha - 1.).abs() < EPS);
+    }
+}
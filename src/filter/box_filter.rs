@@ -1,5 +1,6 @@
-use geometry::Line;
-use super::{Filter, Evaluate};
+use geometry::{Line, QuadraticBezier, CubicBezier};
+use super::{DynamicFilter, Filter, Evaluate, Moments, PiecewisePolynomial};
+use super::piecewise::tile_counts;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct BoxFilter {
@@ -23,6 +24,22 @@ impl Filter for BoxFilter {
     }
 }
 
+impl Moments for BoxFilter {
+    fn moments(&self) -> Option<(DynamicFilter, DynamicFilter)> {
+        // only boxes that span whole tiles can be turned into a `DynamicFilter`
+        let (columns, rows) = match tile_counts(self.support) {
+            Ok(counts) => counts,
+            Err(_) => return None,
+        };
+        let definition = PiecewisePolynomial {
+            name: "Box".to_string(),
+            support: self.support,
+            pieces: vec![vec![vec![vec![1. / self.area as f64]]; columns]; rows],
+        };
+        definition.moments(true).ok()
+    }
+}
+
 impl Evaluate<Line> for BoxFilter {
     fn eval(&self, line: Line, _: (u32, u32)) -> (f32, f32) {
         let accumulator = line.end.y - line.start.y;
@@ -30,3 +47,97 @@ impl Evaluate<Line> for BoxFilter {
         (pixel_value / self.area, accumulator / self.area)
     }
 }
+
+impl Evaluate<QuadraticBezier> for BoxFilter {
+    fn eval(&self, curve: QuadraticBezier, _: (u32, u32)) -> (f32, f32) {
+        let poly = curve.polynomial();
+        let xs = [poly[0].x, poly[1].x, poly[2].x];
+        let ys = [poly[0].y, poly[1].y, poly[2].y];
+
+        let accumulator = curve.end.y - curve.start.y;
+        let pixel_value = green_integral(&xs, &ys);
+        (pixel_value / self.area, accumulator / self.area)
+    }
+}
+
+impl Evaluate<CubicBezier> for BoxFilter {
+    fn eval(&self, curve: CubicBezier, _: (u32, u32)) -> (f32, f32) {
+        let poly = curve.polynomial();
+        let xs = [poly[0].x, poly[1].x, poly[2].x, poly[3].x];
+        let ys = [poly[0].y, poly[1].y, poly[2].y, poly[3].y];
+
+        let accumulator = curve.end.y - curve.start.y;
+        let pixel_value = green_integral(&xs, &ys);
+        (pixel_value / self.area, accumulator / self.area)
+    }
+}
+
+/// Integrates `x dy` along the curve `t -> (x(t), y(t))` for `t` from 0 to 1 where `xs` and `ys`
+/// are the coefficients of `x(t)` and `y(t)` in power basis.
+///
+/// By Green's theorem this is the signed area enclosed between the curve and the y axis.
+fn green_integral(xs: &[f32], ys: &[f32]) -> f32 {
+    let mut integral = 0.;
+    for (i, &x) in xs.iter().enumerate() {
+        for (j, &y) in ys.iter().enumerate().skip(1) {
+            // x_i t^i * j y_j t^(j-1) integrates to j x_i y_j / (i + j)
+            integral += x * y * j as f32 / (i + j) as f32;
+        }
+    }
+    integral
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use geometry::Point;
+
+    const EPS: f32 = 1.0e-6;
+
+    #[test]
+    fn test_quadratic_area() {
+        let filter = BoxFilter::new(1., 1.);
+
+        // the area between the y axis and the parabola x = 2 sqrt(y) - y
+        let curve =
+            QuadraticBezier::new(Point::new(0., 0.), Point::new(1., 0.), Point::new(1., 1.));
+        let (pv, acc) = filter.eval(curve, (0, 0));
+        assert!((pv - 5. / 6.).abs() < EPS);
+        assert!((acc - 1.).abs() < EPS);
+
+        // a straight line gives the same result as the equivalent `Line`
+        let line = Line::new(Point::new(0.25, 0.), Point::new(0.75, 1.));
+        let curve = QuadraticBezier::new(line.start, Point::new(0.5, 0.5), line.end);
+        let (line_pv, line_acc) = filter.eval(line, (0, 0));
+        let (curve_pv, curve_acc) = filter.eval(curve, (0, 0));
+        assert!((line_pv - curve_pv).abs() < EPS);
+        assert!((line_acc - curve_acc).abs() < EPS);
+    }
+
+    #[test]
+    fn test_cubic_area() {
+        let filter = BoxFilter::new(1., 1.);
+
+        // the degree elevation of the parabola above encloses the same area
+        let curve = CubicBezier::new(
+            Point::new(0., 0.),
+            Point::new(2. / 3., 0.),
+            Point::new(1., 1. / 3.),
+            Point::new(1., 1.),
+        );
+        let (pv, acc) = filter.eval(curve, (0, 0));
+        assert!((pv - 5. / 6.).abs() < EPS);
+        assert!((acc - 1.).abs() < EPS);
+
+        // the area between the y axis and the parabola x = 3 y (1 - y), which is 1 / 2
+        let curve = CubicBezier::new(
+            Point::new(0., 0.),
+            Point::new(1., 1. / 3.),
+            Point::new(1., 2. / 3.),
+            Point::new(0., 1.),
+        );
+        let (pv, acc) = filter.eval(curve, (0, 0));
+        assert!((pv - 0.5).abs() < EPS);
+        assert!((acc - 1.).abs() < EPS);
+    }
+}
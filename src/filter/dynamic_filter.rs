@@ -1,13 +1,22 @@
-use serde::Deserialize;
+use rmps;
+use serde::{Deserialize, Serialize};
+use serde_json;
 use simd::f32x4;
 
+use std::error;
+use std::fmt;
 use std::ops::MulAssign;
 
-use geometry::Line;
-use super::{Filter, Evaluate};
+use geometry::{Line, QuadraticBezier, CubicBezier};
+use super::{Filter, Evaluate, Moments};
+use super::piecewise::{self, PiecewisePolynomial, Polynomial};
+
+/// Maximal distance between a curve and the simpler curves it is approximated with when a filter
+/// does not provide tiles for its curve type.
+const APPROXIMATION_TOLERANCE: f32 = 1.0e-3;
 
 trait PowerLookup: Copy + Clone + MulAssign<Self> {
-    type Index: Copy + Clone + Default + ::std::fmt::Debug;
+    type Index: Copy + Clone + Default + ::std::fmt::Debug + AsRef<[u8]>;
     type Output: EvaluateMultinomial;
 
     fn identity() -> Self;
@@ -67,6 +76,61 @@ impl PowerLookup for ParametricLine {
     }
 }
 
+/// A quadratic curve `origin + linear * t + quadratic * t^2`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+struct ParametricQuadratic {
+    origin: [f32; 2],
+    linear: [f32; 2],
+    quadratic: [f32; 2],
+}
+
+impl MulAssign<ParametricQuadratic> for ParametricQuadratic {
+    fn mul_assign(&mut self, other: ParametricQuadratic) {
+        for i in 0..2 {
+            self.origin[i] *= other.origin[i];
+            self.linear[i] *= other.linear[i];
+            self.quadratic[i] *= other.quadratic[i];
+        }
+    }
+}
+
+impl PowerLookup for ParametricQuadratic {
+    type Index = [u8; 6];
+    type Output = [[f32; 4]; 6];
+
+    fn identity() -> Self {
+        ParametricQuadratic {
+            origin: [1., 1.],
+            linear: [1., 1.],
+            quadratic: [1., 1.],
+        }
+    }
+
+    fn power_lookup_4x(table: &[Self], index_mat: [Self::Index; 4]) -> Self::Output {
+        let mut result: [[f32; 4]; 6] = [[0.; 4]; 6];
+
+        for i in 0..6 {
+            let mut row: [f32; 4] = [0.; 4];
+            for (j, index_vec) in index_mat.iter().enumerate() {
+                let entry = &table[index_vec[i] as usize];
+                row[j] = match i {
+                    0 => entry.origin[0],
+                    1 => entry.origin[1],
+                    2 => entry.linear[0],
+                    3 => entry.linear[1],
+                    4 => entry.quadratic[0],
+                    5 => entry.quadratic[1],
+                    _ => unreachable!(),
+                };
+            }
+
+            result[i] = row;
+        }
+
+        result
+    }
+}
+
 impl EvaluateMultinomial for [[f32; 4]; 4] {
     #[inline(always)]
     fn fold(self, mut coeffs: f32x4) -> f32x4 {
@@ -78,19 +142,194 @@ impl EvaluateMultinomial for [[f32; 4]; 4] {
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+impl EvaluateMultinomial for [[f32; 4]; 6] {
+    #[inline(always)]
+    fn fold(self, mut coeffs: f32x4) -> f32x4 {
+        for i in 0..6 {
+            let simd_vec = f32x4::load(&self[i], 0);
+            coeffs = coeffs * simd_vec;
+        }
+        coeffs
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct DynamicFilter {
     name: String,
     support: ((f32, f32), (f32, f32)),
     normalization: f32,
     line_tiles: Option<TileSet<ParametricLine>>,
+    quadratic_tiles: Option<TileSet<ParametricQuadratic>>,
+}
+
+/// The layout of a `DynamicFilter` in MessagePack, where the tile sets are wrapped in an array of
+/// length one.
+#[derive(Deserialize)]
+struct PackedFilter {
+    name: String,
+    support: ((f32, f32), (f32, f32)),
+    normalization: f32,
+    line_tiles: Option<(TileSet<ParametricLine>,)>,
+    quadratic_tiles: Option<(TileSet<ParametricQuadratic>,)>,
+}
+
+#[derive(Serialize)]
+struct PackedFilterRef<'a> {
+    name: &'a str,
+    support: ((f32, f32), (f32, f32)),
+    normalization: f32,
+    line_tiles: Option<(&'a TileSet<ParametricLine>,)>,
+    quadratic_tiles: Option<(&'a TileSet<ParametricQuadratic>,)>,
+}
+
+/// The error returned when a filter can not be decoded.
+#[derive(Debug)]
+pub enum LoadError {
+    Json(serde_json::Error),
+    MessagePack(rmps::decode::Error),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Json(ref err) => write!(f, "invalid JSON filter: {}", err),
+            LoadError::MessagePack(ref err) => write!(f, "invalid MessagePack filter: {}", err),
+        }
+    }
+}
+
+impl error::Error for LoadError {
+    fn description(&self) -> &str {
+        match *self {
+            LoadError::Json(_) => "invalid JSON filter",
+            LoadError::MessagePack(_) => "invalid MessagePack filter",
+        }
+    }
+}
+
+impl DynamicFilter {
+    /// Creates a filter from the polynomials of its tiles, which are given in rows from the top.
+    ///
+    /// Each polynomial is a list of coefficients and the powers of the variables of the curve,
+    /// which are `[x0, y0, x1, y1]` for the line `(x0, y0) + (x1, y1) t` and
+    /// `[x0, y0, x1, y1, x2, y2]` for the quadratic curve `(x0, y0) + (x1, y1) t + (x2, y2) t^2`
+    /// in tile coordinates. The polynomials are scaled by `normalization` when evaluated.
+    pub fn from_polynomials(
+        name: String,
+        support: ((f32, f32), (f32, f32)),
+        normalization: f32,
+        line_tiles: Vec<Vec<Vec<(f32, [u8; 4])>>>,
+        quadratic_tiles: Option<Vec<Vec<Vec<(f32, [u8; 6])>>>>,
+    ) -> DynamicFilter {
+        DynamicFilter {
+            name: name,
+            support: support,
+            normalization: normalization,
+            line_tiles: Some(TileSet::from_polynomials(line_tiles)),
+            quadratic_tiles: quadratic_tiles.map(TileSet::from_polynomials),
+        }
+    }
+
+    /// Decodes a filter from either JSON or MessagePack.
+    ///
+    /// The format is detected from the first byte, since a JSON filter is an object while
+    /// MessagePack maps start with a byte that is not printable.
+    pub fn from_slice(data: &[u8]) -> Result<DynamicFilter, LoadError> {
+        let first = data.iter().find(|&&byte| !(byte as char).is_whitespace());
+        if first == Some(&b'{') {
+            return serde_json::from_slice(data).map_err(LoadError::Json);
+        }
+        let packed: PackedFilter = rmps::from_slice(data).map_err(LoadError::MessagePack)?;
+        Ok(DynamicFilter {
+            name: packed.name,
+            support: packed.support,
+            normalization: packed.normalization,
+            line_tiles: packed.line_tiles.map(|tiles| tiles.0),
+            quadratic_tiles: packed.quadratic_tiles.map(|tiles| tiles.0),
+        })
+    }
+
+    /// Encodes the filter as JSON.
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("failed to encode filter")
+    }
+
+    /// Encodes the filter as MessagePack in the layout of the filters shipped with the crate.
+    pub fn to_message_pack(&self) -> Vec<u8> {
+        let packed = PackedFilterRef {
+            name: &self.name,
+            support: self.support,
+            normalization: self.normalization,
+            line_tiles: self.line_tiles.as_ref().map(|tiles| (tiles,)),
+            quadratic_tiles: self.quadratic_tiles.as_ref().map(|tiles| (tiles,)),
+        };
+        rmps::to_vec_named(&packed).expect("failed to encode filter")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Recovers the normalized filter as a polynomial on each tile from the line tiles.
+    ///
+    /// The tile of a vertical line from `(u, 0)` to `(u, v)` is the integral of the filter over
+    /// `[0, u] x [0, v]`, which gives the filter after differentiating once in `u` and once in `v`.
+    pub fn kernel(&self) -> Option<PiecewisePolynomial> {
+        let tiles = match self.line_tiles {
+            Some(ref tiles) => tiles,
+            None => return None,
+        };
+        let pieces = tiles
+            .0
+            .iter()
+            .enumerate()
+            .map(|(row, tiles)| {
+                tiles
+                    .iter()
+                    .enumerate()
+                    .map(|(column, tile)| {
+                        let mut local: Polynomial = Vec::new();
+                        for (&c, powers) in tile.coefficients.iter().zip(&tile.powers) {
+                            let (m, n) = (powers[0] as usize, powers[3] as usize);
+                            if powers[1] != 0 || powers[2] != 0 || m == 0 || n == 0 {
+                                continue;
+                            }
+                            if local.len() < m {
+                                local.resize(m, Vec::new());
+                            }
+                            if local[m - 1].len() < n {
+                                local[m - 1].resize(n, 0.);
+                            }
+                            local[m - 1][n - 1] +=
+                                (c * self.normalization) as f64 * (m * n) as f64;
+                        }
+                        let (x, y) = piecewise::tile_origin(self.support, column, row);
+                        piecewise::shift(&local, -x, -y)
+                    })
+                    .collect()
+            })
+            .collect();
+        Some(PiecewisePolynomial {
+            name: self.name.clone(),
+            support: self.support,
+            pieces: pieces,
+        })
+    }
 }
 
-#[serde(bound = "Tile<T>: Deserialize<'de>")]
-#[derive(Debug, Deserialize, Clone)]
+#[serde(bound(serialize = "Tile<T>: Serialize", deserialize = "Tile<T>: Deserialize<'de>"))]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct TileSet<T: PowerLookup>(Vec<Vec<Tile<T>>>);
 
 impl<T: PowerLookup> TileSet<T> {
+    fn from_polynomials(rows: Vec<Vec<Vec<(f32, T::Index)>>>) -> TileSet<T> {
+        TileSet(
+            rows.into_iter()
+                .map(|row| row.into_iter().map(Tile::from_polynomial).collect())
+                .collect(),
+        )
+    }
+
     /// Evaluates the specified tile using supplied values.
     fn evaluate_tile(&self, tile: (u32, u32), values: T) -> f32 {
         let tile = &self.0[tile.1 as usize][tile.0 as usize];
@@ -100,8 +339,8 @@ impl<T: PowerLookup> TileSet<T> {
     }
 }
 
-#[serde(bound = "T::Index: Deserialize<'de>")]
-#[derive(Debug, Deserialize, Clone)]
+#[serde(bound(serialize = "T::Index: Serialize", deserialize = "T::Index: Deserialize<'de>"))]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 struct Tile<T: PowerLookup> {
     coefficients: Vec<f32>,
     powers: Vec<T::Index>,
@@ -109,6 +348,20 @@ struct Tile<T: PowerLookup> {
 }
 
 impl<T: PowerLookup> Tile<T> {
+    fn from_polynomial(terms: Vec<(f32, T::Index)>) -> Tile<T> {
+        // the lookup table always contains the first power
+        let max_pow = terms
+            .iter()
+            .flat_map(|term| term.1.as_ref().iter().cloned())
+            .fold(1, u8::max);
+        let (coefficients, powers) = terms.into_iter().unzip();
+        Tile {
+            coefficients: coefficients,
+            powers: powers,
+            max_pow: max_pow,
+        }
+    }
+
     fn evaluate(&self, lookup_4x_table: &PowersLookupTable<T>) -> f32 {
         let mut result = f32x4::splat(0.0);
 
@@ -177,6 +430,13 @@ impl Filter for DynamicFilter {
     }
 }
 
+impl Moments for DynamicFilter {
+    fn moments(&self) -> Option<(DynamicFilter, DynamicFilter)> {
+        let quadratic = self.quadratic_tiles.is_some();
+        self.kernel().and_then(|kernel| kernel.moments(quadratic).ok())
+    }
+}
+
 impl Evaluate<Line> for DynamicFilter {
     fn eval(&self, line: Line, piece: (u32, u32)) -> (f32, f32) {
         let line_tileset = self.line_tiles.as_ref().expect(
@@ -199,12 +459,57 @@ impl Evaluate<Line> for DynamicFilter {
     }
 }
 
+impl Evaluate<QuadraticBezier> for DynamicFilter {
+    fn eval(&self, curve: QuadraticBezier, piece: (u32, u32)) -> (f32, f32) {
+        let quadratic_tileset = match self.quadratic_tiles {
+            Some(ref tileset) => tileset,
+            // Filters without quadratic tiles can still rasterize curves by approximating them.
+            None => {
+                return curve.flatten(APPROXIMATION_TOLERANCE).fold(
+                    (0., 0.),
+                    |(pixel_value, accumulator), line| {
+                        let (pv, acc) = self.eval(line, piece);
+                        (pixel_value + pv, accumulator + acc)
+                    },
+                )
+            }
+        };
+
+        let poly = curve.polynomial();
+        let mut par_quad = ParametricQuadratic {
+            origin: [poly[0].x, poly[0].y],
+            linear: [poly[1].x, poly[1].y],
+            quadratic: [poly[2].x, poly[2].y],
+        };
+        let pixel_value = quadratic_tileset.evaluate_tile(piece, par_quad);
+        par_quad.origin[0] = 1.0;
+        par_quad.linear[0] = 0.0;
+        par_quad.quadratic[0] = 0.0;
+        let accumulator = quadratic_tileset.evaluate_tile(piece, par_quad);
+
+        (
+            pixel_value * self.normalization,
+            accumulator * self.normalization,
+        )
+    }
+}
+
+impl Evaluate<CubicBezier> for DynamicFilter {
+    fn eval(&self, curve: CubicBezier, piece: (u32, u32)) -> (f32, f32) {
+        curve.to_quadratics(APPROXIMATION_TOLERANCE).fold(
+            (0., 0.),
+            |(pixel_value, accumulator), quad| {
+                let (pv, acc) = self.eval(quad, piece);
+                (pixel_value + pv, accumulator + acc)
+            },
+        )
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use geometry::Point;
-
-    use rmps;
+    use geometry::{Point, QuadraticBezier, CubicBezier};
 
     const EPS: f32 = 1.0e-5;
 
@@ -247,6 +552,74 @@ mod test {
 
     use itertools::Itertools;
 
+    #[test]
+    fn test_load() {
+        let packed = DynamicFilter::from_slice(include_bytes!("../../Gaussian.filter")).unwrap();
+        let json = DynamicFilter::from_slice(include_bytes!("../../Gaussian.json")).unwrap();
+        assert_eq!(packed.name(), "Gaussian");
+        assert_eq!(packed.support(), json.support());
+        assert_eq!(packed.normalization, json.normalization);
+        assert!(packed.quadratic_tiles.is_none());
+
+        let line = Line {
+            start: Point::new(-0.2, -0.4),
+            end: Point::new(0.3, 0.5),
+        };
+        assert_eq!(packed.eval(line, (1, 1)), json.eval(line, (1, 1)));
+
+        match DynamicFilter::from_slice(b"  {\"name\": 1}") {
+            Err(LoadError::Json(_)) => {}
+            _ => panic!("expected a JSON error"),
+        }
+        match DynamicFilter::from_slice(&[0x80]) {
+            Err(LoadError::MessagePack(_)) => {}
+            _ => panic!("expected a MessagePack error"),
+        }
+    }
+
+    #[test]
+    fn test_kernel() {
+        // the tent filter `(1 - |x|) (1 - |y|)`
+        let tent = PiecewisePolynomial {
+            name: "Tent".to_string(),
+            support: ((-1., 1.), (-1., 1.)),
+            pieces: vec![
+                vec![vec![vec![1., 1.], vec![1., 1.]], vec![vec![1., 1.], vec![-1., -1.]]],
+                vec![vec![vec![1., -1.], vec![1., -1.]], vec![vec![1., -1.], vec![-1., 1.]]],
+            ],
+        };
+        let filter = tent.to_filter(true).unwrap();
+        let kernel = filter.kernel().unwrap();
+        for (row, expected_row) in kernel.pieces.iter().zip(&tent.pieces) {
+            for (piece, expected) in row.iter().zip(expected_row) {
+                for (m, coefficients) in expected.iter().enumerate() {
+                    for (n, &c) in coefficients.iter().enumerate() {
+                        assert!((piece[m][n] - c).abs() < 1.0e-5);
+                    }
+                }
+            }
+        }
+
+        // the top left tile covers `[-1, 0] x [-1, 0]`, where `x f(x, y)` integrates to -1/12
+        let (x_moment, y_moment) = filter.moments().unwrap();
+        let line = Line {
+            start: Point::new(1., 0.),
+            end: Point::new(1., 1.),
+        };
+        assert!((x_moment.eval(line, (0, 0)).0 + 1. / 12.).abs() < EPS);
+        assert!((y_moment.eval(line, (1, 0)).0 + 1. / 12.).abs() < EPS);
+        assert!((x_moment.eval(line, (1, 0)).0 - 1. / 12.).abs() < EPS);
+
+        // the filters shipped with the crate are symmetric
+        let lanczos = DynamicFilter::from_slice(include_bytes!("../../lanczos.filter")).unwrap();
+        let (x_moment, _) = lanczos.moments().unwrap();
+        let total = (0..6)
+            .flat_map(|x| (0..6).map(move |y| (x, y)))
+            .map(|piece| (lanczos.eval(line, piece).0, x_moment.eval(line, piece).0))
+            .fold((0., 0.), |sum, (value, moment)| (sum.0 + value, sum.1 + moment));
+        assert!((total.0 - 1.).abs() < 1.0e-4 && total.1.abs() < 1.0e-4);
+    }
+
     #[test]
     fn test_power_table() {
         let line = ParametricLine {
@@ -271,4 +644,82 @@ mod test {
             assert!((line.vector[1].powi(index_mat[i][3] as i32) - powers_mat[3][i]) < EPS);
         }
     }
+
+    #[test]
+    fn test_quadratic_power_table() {
+        let curve = ParametricQuadratic {
+            origin: [1.0, 2.0],
+            linear: [3.0, 4.0],
+            quadratic: [0.5, -1.5],
+        };
+
+        let pow_tab = PowersLookupTable::new(curve, 4);
+
+        let index_mat = [
+            [0, 1, 2, 3, 4, 0],
+            [4, 3, 2, 1, 0, 1],
+            [1, 0, 0, 2, 3, 4],
+            [2, 2, 2, 2, 2, 2],
+        ];
+
+        let powers_mat = pow_tab.lookup_4x(index_mat);
+
+        let values = [
+            curve.origin[0],
+            curve.origin[1],
+            curve.linear[0],
+            curve.linear[1],
+            curve.quadratic[0],
+            curve.quadratic[1],
+        ];
+        for (i, index_vec) in index_mat.iter().enumerate() {
+            for (var, &value) in values.iter().enumerate() {
+                let expected = value.powi(index_vec[var] as i32);
+                assert!((expected - powers_mat[var][i]).abs() < EPS);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quadratic_without_tiles() {
+        let data = include_bytes!("../../lanczos.json");
+        let lanczos: DynamicFilter = serde_json::from_slice(data).unwrap();
+        assert!(lanczos.quadratic_tiles.is_none());
+
+        // a quadratic curve with its control point on the chord is a straight line
+        let line = Line {
+            start: Point::new(0.2, 0.1),
+            end: Point::new(0.8, 0.9),
+        };
+        let curve = QuadraticBezier::new(line.start, Point::new(0.5, 0.5), line.end);
+
+        let (line_pv, line_acc) = lanczos.eval(line, (2, 3));
+        let (curve_pv, curve_acc) = lanczos.eval(curve, (2, 3));
+        assert!((line_pv - curve_pv).abs() < EPS);
+        assert!((line_acc - curve_acc).abs() < EPS);
+    }
+
+    #[test]
+    fn test_cubic_approximation() {
+        let data = include_bytes!("../../lanczos.json");
+        let lanczos: DynamicFilter = serde_json::from_slice(data).unwrap();
+
+        let quad = QuadraticBezier::new(
+            Point::new(0.1, 0.2),
+            Point::new(0.9, 0.3),
+            Point::new(0.6, 0.8),
+        );
+        // degree elevation of `quad`
+        let cubic = CubicBezier::new(
+            quad.start,
+            quad.start + (quad.control - quad.start) * (2. / 3.),
+            quad.end + (quad.control - quad.end) * (2. / 3.),
+            quad.end,
+        );
+
+        let (quad_pv, quad_acc) = lanczos.eval(quad, (3, 1));
+        let (cubic_pv, cubic_acc) = lanczos.eval(cubic, (3, 1));
+        assert!((quad_pv - cubic_pv).abs() < 1.0e-4);
+        assert!((quad_acc - cubic_acc).abs() < 1.0e-4);
+    }
 }
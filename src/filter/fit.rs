@@ -0,0 +1,315 @@
+//! Approximates arbitrary kernels with a polynomial on each tile, so that they can be turned into
+//! a `DynamicFilter`.
+
+use std::f64::consts::PI;
+
+use super::piecewise::{self, DefinitionError, PiecewisePolynomial, Polynomial};
+
+/// The approximation of a kernel by a polynomial on each tile.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KernelFit {
+    pub definition: PiecewisePolynomial,
+    /// The largest absolute difference between the kernel and the polynomials.
+    pub max_error: f64,
+}
+
+/// The highest degree that `fit_kernel` supports when the tiles are computed for quadratic
+/// curves, whose tile polynomials contain powers of the curve parameter up to `4 degree + 3` that
+/// must fit in a `u8`.
+pub const MAX_DEGREE: usize = 63;
+
+/// Fits a polynomial with powers of `x` and `y` up to `degree` to `kernel(x, y)` on each tile of
+/// `support` using least squares.
+///
+/// The kernel is sampled on a grid of `4 (degree + 1)` points per direction on each tile, while
+/// the error is measured on a twice as fine grid that includes the edges of the tiles.
+pub fn fit_kernel<K>(
+    name: &str,
+    support: ((f32, f32), (f32, f32)),
+    degree: usize,
+    kernel: K,
+) -> Result<KernelFit, DefinitionError>
+where
+    K: Fn(f64, f64) -> f64,
+{
+    let (columns, rows) = piecewise::tile_counts(support)?;
+    let samples = 4 * (degree + 1);
+    let mut max_error = 0.;
+    let pieces = (0..rows)
+        .map(|row| {
+            (0..columns)
+                .map(|column| {
+                    let (x, y) = piecewise::tile_origin(support, column, row);
+                    let local = fit_tile(|u, v| kernel(x + u, y + v), degree, samples);
+                    let error_samples = 2 * samples;
+                    for i in 0..error_samples + 1 {
+                        for j in 0..error_samples + 1 {
+                            let u = i as f64 / error_samples as f64;
+                            let v = j as f64 / error_samples as f64;
+                            let error = (evaluate(&local, u, v) - kernel(x + u, y + v)).abs();
+                            max_error = error.max(max_error);
+                        }
+                    }
+                    piecewise::shift(&local, -x, -y)
+                })
+                .collect()
+        })
+        .collect();
+    Ok(KernelFit {
+        definition: PiecewisePolynomial {
+            name: name.to_string(),
+            support: support,
+            pieces: pieces,
+        },
+        max_error: max_error,
+    })
+}
+
+/// Fits a polynomial in tile coordinates to `kernel` on the unit square.
+fn fit_tile<K: Fn(f64, f64) -> f64>(kernel: K, degree: usize, samples: usize) -> Polynomial {
+    let terms = (degree + 1) * (degree + 1);
+    // the columns of the design matrix, one for each power `u^m v^n`
+    let mut columns = vec![Vec::with_capacity(samples * samples); terms];
+    let mut values = Vec::with_capacity(samples * samples);
+    for i in 0..samples {
+        for j in 0..samples {
+            let u = (i as f64 + 0.5) / samples as f64;
+            let v = (j as f64 + 0.5) / samples as f64;
+            for (index, column) in columns.iter_mut().enumerate() {
+                let (m, n) = (index / (degree + 1), index % (degree + 1));
+                column.push(u.powi(m as i32) * v.powi(n as i32));
+            }
+            values.push(kernel(u, v));
+        }
+    }
+    let solution = least_squares(columns, values);
+    solution.chunks(degree + 1).map(|row| row.to_vec()).collect()
+}
+
+/// Returns the `x` minimizing `|A x - b|` where `A` is given by its columns, which have to be
+/// linearly independent.
+///
+/// This uses a QR decomposition by Householder reflections, which is much better conditioned
+/// than solving the normal equations.
+fn least_squares(mut columns: Vec<Vec<f64>>, mut b: Vec<f64>) -> Vec<f64> {
+    let n = columns.len();
+    let mut diagonal = vec![0.; n];
+    for k in 0..n {
+        let norm = columns[k][k..].iter().map(|a| a * a).sum::<f64>().sqrt();
+        let alpha = if columns[k][k] > 0. { -norm } else { norm };
+        // the reflection maps the column to `alpha e_k`
+        let mut reflector = columns[k][k..].to_vec();
+        reflector[0] -= alpha;
+        let norm_squared = reflector.iter().map(|a| a * a).sum::<f64>();
+        diagonal[k] = alpha;
+        if norm_squared == 0. {
+            continue;
+        }
+        let reflect = |vector: &mut [f64]| {
+            let dot = reflector.iter().zip(vector.iter()).map(|(a, b)| a * b).sum::<f64>();
+            let scale = 2. * dot / norm_squared;
+            for (x, r) in vector.iter_mut().zip(&reflector) {
+                *x -= scale * r;
+            }
+        };
+        for column in &mut columns[k + 1..] {
+            reflect(&mut column[k..]);
+        }
+        reflect(&mut b[k..]);
+    }
+
+    // back substitution with the upper triangular matrix
+    let mut x = vec![0.; n];
+    for k in (0..n).rev() {
+        let sum = (k + 1..n).map(|j| columns[j][k] * x[j]).sum::<f64>();
+        x[k] = (b[k] - sum) / diagonal[k];
+    }
+    x
+}
+
+fn evaluate(p: &Polynomial, x: f64, y: f64) -> f64 {
+    p.iter().rev().fold(0., |result, coefficients| {
+        result * x + coefficients.iter().rev().fold(0., |result, &c| result * y + c)
+    })
+}
+
+/// A kernel given by values on a regular grid, which are interpolated bilinearly.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SampledKernel {
+    pub support: ((f32, f32), (f32, f32)),
+    /// The values in rows from the top, where the first and last row and column lie on the edges
+    /// of the support.
+    pub values: Vec<Vec<f64>>,
+}
+
+impl SampledKernel {
+    /// Checks that the values form a grid of at least two rows and columns, which is required by
+    /// `value`.
+    pub fn validate(&self) -> Result<(), DefinitionError> {
+        let columns = self.values.first().map_or(0, |row| row.len());
+        let is_grid = self.values.iter().all(|row| row.len() == columns);
+        if !is_grid || self.values.len() < 2 || columns < 2 {
+            return Err(DefinitionError::Samples);
+        }
+        Ok(())
+    }
+
+    /// Returns the interpolated value at `(x, y)`, which is zero outside of the support.
+    ///
+    /// The values have to pass `validate`.
+    pub fn value(&self, x: f64, y: f64) -> f64 {
+        let ((x0, x1), (y0, y1)) = self.support;
+        let (rows, columns) = (self.values.len(), self.values[0].len());
+        let s = (x - x0 as f64) / (x1 - x0) as f64 * (columns - 1) as f64;
+        let t = (y - y0 as f64) / (y1 - y0) as f64 * (rows - 1) as f64;
+        if s < 0. || t < 0. || s > (columns - 1) as f64 || t > (rows - 1) as f64 {
+            return 0.;
+        }
+        let (i, j) = ((s as usize).min(columns - 2), (t as usize).min(rows - 2));
+        let (s, t) = (s - i as f64, t - j as f64);
+        let row = |j: usize| self.values[j][i] * (1. - s) + self.values[j][i + 1] * s;
+        row(j) * (1. - t) + row(j + 1) * t
+    }
+}
+
+/// The Mitchell-Netravali cubic with the parameters `b` and `c`, which vanishes beyond `|x| = 2`.
+pub fn mitchell_netravali(b: f64, c: f64, x: f64) -> f64 {
+    let x = x.abs();
+    let value = if x < 1. {
+        (12. - 9. * b - 6. * c) * x * x * x + (-18. + 12. * b + 6. * c) * x * x + (6. - 2. * b)
+    } else if x < 2. {
+        (-b - 6. * c) * x * x * x + (6. * b + 30. * c) * x * x + (-12. * b - 48. * c) * x +
+            (8. * b + 24. * c)
+    } else {
+        0.
+    };
+    value / 6.
+}
+
+/// The Kaiser window of the given radius, where `beta` controls the trade-off between the width
+/// of the main lobe and the height of the side lobes.
+pub fn kaiser(radius: f64, beta: f64, x: f64) -> f64 {
+    let r = x / radius;
+    if r.abs() > 1. {
+        return 0.;
+    }
+    bessel_i0(beta * (1. - r * r).sqrt()) / bessel_i0(beta)
+}
+
+/// The four-term Blackman-Harris window of the given radius.
+pub fn blackman_harris(radius: f64, x: f64) -> f64 {
+    if x.abs() > radius {
+        return 0.;
+    }
+    let phase = 2. * PI * (x + radius) / (2. * radius);
+    0.35875 - 0.48829 * phase.cos() + 0.14128 * (2. * phase).cos() - 0.01168 * (3. * phase).cos()
+}
+
+/// The modified Bessel function of the first kind of order zero.
+fn bessel_i0(x: f64) -> f64 {
+    // the power series `sum ((x / 2)^k / k!)^2` converges quickly for moderate `x`
+    let mut term = 1.;
+    let mut sum = 1.;
+    let mut k = 1.;
+    while term > 1.0e-16 * sum {
+        term *= (x / (2. * k)) * (x / (2. * k));
+        sum += term;
+        k += 1.;
+    }
+    sum
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use filter::{DynamicFilter, Evaluate};
+    use geometry::{Line, Point};
+
+    const EPS: f32 = 1.0e-5;
+
+    #[test]
+    fn test_least_squares() {
+        // the line through (0, 1), (1, 2) and (2, 5) closest to them is `2 x + 2 / 3`
+        let columns = vec![vec![1., 1., 1.], vec![0., 1., 2.]];
+        let x = least_squares(columns, vec![1., 2., 5.]);
+        assert!((x[0] - 2. / 3.).abs() < 1.0e-12 && (x[1] - 2.).abs() < 1.0e-12);
+    }
+
+    #[test]
+    fn test_piecewise_polynomial_kernel() {
+        // the Mitchell-Netravali filter is cubic on each tile, so it is reproduced exactly
+        let mitchell = |x: f64| mitchell_netravali(1. / 3., 1. / 3., x);
+        let kernel = |x, y| mitchell(x) * mitchell(y);
+        let fit = fit_kernel("Mitchell", ((-2., 2.), (-2., 2.)), 3, kernel).unwrap();
+        assert!(fit.max_error < 1.0e-10);
+        let piece = &fit.definition.pieces[1][2];
+        assert!((evaluate(piece, 0.25, -0.5) - mitchell(0.25) * mitchell(-0.5)).abs() < 1.0e-10);
+
+        // the pieces integrate to one like the kernel itself, before `to_filter` normalizes them
+        let support = fit.definition.support;
+        let mut integral = 0.;
+        for (row, pieces) in fit.definition.pieces.iter().enumerate() {
+            for (column, piece) in pieces.iter().enumerate() {
+                let (x, y) = piecewise::tile_origin(support, column, row);
+                let local = piecewise::shift(piece, x, y);
+                for (m, coefficients) in local.iter().enumerate() {
+                    for (n, c) in coefficients.iter().enumerate() {
+                        integral += c / ((m + 1) * (n + 1)) as f64;
+                    }
+                }
+            }
+        }
+        assert!((integral - 1.).abs() < 1.0e-10);
+
+        // the tiles integrate the kernel over the area left of the line, here `0 <= x <= 0.5`
+        let filter = fit.definition.to_filter(false).unwrap();
+        let sampled = DynamicFilter::from_slice(&filter.to_json()).unwrap();
+        let line = Line::new(Point::new(0.5, 0.), Point::new(0.5, 1.));
+        let total = (0..4).map(|y| sampled.eval(line, (2, y)).0).sum::<f32>();
+        // the antiderivative of the cubic for `|x| < 1` at 0.5
+        let expected = (7. / 4. * 0.5f32.powi(4) - 4. * 0.5f32.powi(3) + 16. / 3. * 0.5) / 6.;
+        assert!((total - expected).abs() < EPS);
+    }
+
+    #[test]
+    fn test_windows() {
+        assert_eq!(kaiser(2., 4., 0.), 1.);
+        assert!((kaiser(2., 4., 2.) - 1. / bessel_i0(4.)).abs() < 1.0e-12);
+        assert!((bessel_i0(1.) - 1.266_065_877_752_008_4).abs() < 1.0e-12);
+        assert!((blackman_harris(2., 0.) - 1.).abs() < 1.0e-12);
+        assert!(blackman_harris(2., 2.).abs() < 1.0e-4);
+
+        // the error of the approximation decreases with its degree
+        let window = |x: f64, y: f64| blackman_harris(2., x) * blackman_harris(2., y);
+        let errors = [2, 4]
+            .iter()
+            .map(|&degree| fit_kernel("Blackman-Harris", ((-2., 2.), (-2., 2.)), degree, &window))
+            .map(|fit| fit.unwrap().max_error)
+            .collect::<Vec<_>>();
+        assert!(errors[1] < errors[0] && errors[1] < 1.0e-2);
+    }
+
+    #[test]
+    fn test_sampled_kernel() {
+        let kernel = SampledKernel {
+            support: ((-1., 1.), (-1., 1.)),
+            values: vec![vec![0., 0., 0.], vec![0., 1., 0.], vec![0., 0., 0.]],
+        };
+        assert_eq!(kernel.value(0., 0.), 1.);
+        assert_eq!(kernel.value(-0.5, 0.5), 0.25);
+        assert_eq!(kernel.value(1.5, 0.), 0.);
+        assert_eq!(kernel.validate(), Ok(()));
+
+        // all rows need the same number of values
+        let jagged = SampledKernel {
+            support: kernel.support,
+            values: vec![vec![0., 0., 0.], vec![0., 1.], vec![0., 0., 0.]],
+        };
+        assert_eq!(jagged.validate(), Err(DefinitionError::Samples));
+
+        // bilinear interpolation between samples on the corners of the tiles is exact
+        let fit = fit_kernel("Tent", kernel.support, 1, |x, y| kernel.value(x, y)).unwrap();
+        assert!(fit.max_error < 1.0e-12);
+        assert!(fit.definition.to_filter(false).is_ok());
+    }
+}
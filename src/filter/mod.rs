@@ -1,15 +1,42 @@
+use geometry::{Line, QuadraticBezier, CubicBezier, Segment};
+
 mod box_filter;
 mod dynamic_filter;
+mod fit;
+mod piecewise;
 
 pub use self::box_filter::BoxFilter;
-pub use self::dynamic_filter::DynamicFilter;
+pub use self::dynamic_filter::{DynamicFilter, LoadError};
+pub use self::fit::{KernelFit, SampledKernel, MAX_DEGREE, fit_kernel};
+pub use self::fit::{mitchell_netravali, kaiser, blackman_harris};
+pub use self::piecewise::{PiecewisePolynomial, Polynomial, DefinitionError};
 
 pub trait Filter {
     /// Returns the support of the filter
     fn support(&self) -> ((f32, f32), (f32, f32));
 }
 
+/// A filter that can be weighted with the position relative to its center.
+pub trait Moments {
+    /// Returns the filters `x f(x, y)` and `y f(x, y)` for this filter `f`, or `None` if they can
+    /// not be expressed with tiles.
+    fn moments(&self) -> Option<(DynamicFilter, DynamicFilter)>;
+}
+
 pub trait Evaluate<C> {
     // second return value is accumulator
     fn eval(&self, curve: C, filter_piece: (u32, u32)) -> (f32, f32);
-}
\ No newline at end of file
+}
+
+impl<F> Evaluate<Segment> for F
+where
+    F: Evaluate<Line> + Evaluate<QuadraticBezier> + Evaluate<CubicBezier>,
+{
+    fn eval(&self, segment: Segment, filter_piece: (u32, u32)) -> (f32, f32) {
+        match segment {
+            Segment::Line(line) => self.eval(line, filter_piece),
+            Segment::Quadratic(curve) => self.eval(curve, filter_piece),
+            Segment::Cubic(curve) => self.eval(curve, filter_piece),
+        }
+    }
+}
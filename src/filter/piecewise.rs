@@ -0,0 +1,448 @@
+//! Computes the tiles of a `DynamicFilter` from a filter that is a polynomial on each tile.
+//!
+//! The value of a tile for a curve is the integral of `F(x, y) dy` along the curve, where `F` is
+//! the integral of the filter along a row of the tile from its left edge up to `x`. By Green's
+//! theorem, summing it over a closed outline gives the integral of the filter over the enclosed
+//! area. Substituting the curve into `F` turns the integral into a polynomial in the coefficients
+//! of the curve, which is computed exactly.
+
+use std::collections::BTreeMap;
+use std::error;
+use std::fmt;
+use std::iter;
+
+use super::DynamicFilter;
+
+/// The coefficients `c[m][n]` of `x^m y^n`.
+pub type Polynomial = Vec<Vec<f64>>;
+
+/// A filter given by a polynomial in `x` and `y` on each unit square of its support.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PiecewisePolynomial {
+    pub name: String,
+    /// The ranges of `x` and `y` where the filter is non-zero. Both have to span a whole number
+    /// of units.
+    pub support: ((f32, f32), (f32, f32)),
+    /// The polynomials in rows from the top, such that `pieces[j][i]` applies to the square `i`
+    /// units right of and `j` units below the top left corner of the support.
+    ///
+    /// The polynomials are in the coordinates relative to the center of the filter.
+    pub pieces: Vec<Vec<Polynomial>>,
+}
+
+/// The reason why a `PiecewisePolynomial` or a `SampledKernel` does not describe a filter.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum DefinitionError {
+    /// The width or height of the support is not a positive whole number.
+    Support,
+    /// The number of rows or columns of pieces does not match the support.
+    Pieces,
+    /// The integral of the filter is zero.
+    Integral,
+    /// The samples do not form a grid of at least two rows and columns.
+    Samples,
+}
+
+impl DefinitionError {
+    fn message(&self) -> &'static str {
+        match *self {
+            DefinitionError::Support => "the support does not span a whole number of units",
+            DefinitionError::Pieces => "the pieces do not cover the support",
+            DefinitionError::Integral => "the integral of the filter is zero",
+            DefinitionError::Samples => "the samples do not form a grid of at least 2 x 2 values",
+        }
+    }
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl error::Error for DefinitionError {
+    fn description(&self) -> &str {
+        self.message()
+    }
+}
+
+impl PiecewisePolynomial {
+    /// Returns the number of columns and rows of tiles.
+    fn tile_counts(&self) -> Result<(usize, usize), DefinitionError> {
+        let (columns, rows) = tile_counts(self.support)?;
+        if self.pieces.len() != rows || self.pieces.iter().any(|row| row.len() != columns) {
+            return Err(DefinitionError::Pieces);
+        }
+        Ok((columns, rows))
+    }
+
+    /// Returns the polynomial of the tile in the given column and row in tile coordinates, which
+    /// range from 0 to 1 across the tile.
+    fn tile_polynomial(&self, column: usize, row: usize) -> Polynomial {
+        let (x, y) = tile_origin(self.support, column, row);
+        shift(&self.pieces[row][column], x, y)
+    }
+
+    /// Returns the polynomials of all tiles in tile coordinates.
+    fn tiles(&self) -> Result<Vec<Vec<Polynomial>>, DefinitionError> {
+        let (columns, rows) = self.tile_counts()?;
+        Ok(
+            (0..rows)
+                .map(|row| {
+                    (0..columns)
+                        .map(|column| self.tile_polynomial(column, row))
+                        .collect()
+                })
+                .collect(),
+        )
+    }
+
+    /// Computes the tiles for lines, and for quadratic curves if `quadratic` is set.
+    pub fn to_filter(&self, quadratic: bool) -> Result<DynamicFilter, DefinitionError> {
+        let tiles = self.tiles()?;
+        let integral = tiles
+            .iter()
+            .flat_map(|row| row.iter())
+            .map(|tile| {
+                let mut integral = 0.;
+                for (m, coefficients) in tile.iter().enumerate() {
+                    for (n, &c) in coefficients.iter().enumerate() {
+                        integral += c / ((m + 1) * (n + 1)) as f64;
+                    }
+                }
+                integral
+            })
+            .sum::<f64>();
+        if integral == 0. {
+            return Err(DefinitionError::Integral);
+        }
+        Ok(self.filter_from_tiles(&tiles, quadratic, (1. / integral) as f32))
+    }
+
+    /// Computes the filters `x f(x, y)` and `y f(x, y)`, which weight the filter `f` with the
+    /// position relative to its center.
+    ///
+    /// Unlike the filter computed by `to_filter`, these are not normalized, so the pieces should
+    /// be normalized already.
+    pub fn moments(
+        &self,
+        quadratic: bool,
+    ) -> Result<(DynamicFilter, DynamicFilter), DefinitionError> {
+        let moment = |pieces: Vec<Vec<Polynomial>>| {
+            let moment = PiecewisePolynomial {
+                name: self.name.clone(),
+                support: self.support,
+                pieces: pieces,
+            };
+            moment.tiles().map(|tiles| moment.filter_from_tiles(&tiles, quadratic, 1.))
+        };
+        // multiplying with `x` or `y` raises the powers by one
+        let times_x = |piece: &Polynomial| {
+            iter::once(Vec::new())
+                .chain(piece.iter().cloned())
+                .collect::<Polynomial>()
+        };
+        let times_y = |piece: &Polynomial| {
+            piece
+                .iter()
+                .map(|coefficients| iter::once(0.).chain(coefficients.iter().cloned()).collect())
+                .collect::<Polynomial>()
+        };
+        let x = moment(
+            self.pieces
+                .iter()
+                .map(|row| row.iter().map(|piece| times_x(piece)).collect())
+                .collect(),
+        )?;
+        let y = moment(
+            self.pieces
+                .iter()
+                .map(|row| row.iter().map(|piece| times_y(piece)).collect())
+                .collect(),
+        )?;
+        Ok((x, y))
+    }
+
+    fn filter_from_tiles(
+        &self,
+        tiles: &[Vec<Polynomial>],
+        quadratic: bool,
+        normalization: f32,
+    ) -> DynamicFilter {
+        let line_tiles = tile_polynomials(tiles, 1, |p| [p[0], p[1], p[2], p[3]]);
+        let quadratic_tiles = if quadratic {
+            Some(tile_polynomials(tiles, 2, |p| [p[0], p[1], p[2], p[3], p[4], p[5]]))
+        } else {
+            None
+        };
+        DynamicFilter::from_polynomials(
+            self.name.clone(),
+            self.support,
+            normalization,
+            line_tiles,
+            quadratic_tiles,
+        )
+    }
+}
+
+/// Returns the number of columns and rows of tiles that cover `support`.
+pub fn tile_counts(support: ((f32, f32), (f32, f32))) -> Result<(usize, usize), DefinitionError> {
+    let ((x0, x1), (y0, y1)) = support;
+    let (width, height) = (x1 - x0, y1 - y0);
+    if width < 1. || height < 1. || width.fract() != 0. || height.fract() != 0. {
+        return Err(DefinitionError::Support);
+    }
+    Ok((width as usize, height as usize))
+}
+
+/// Returns the top left corner of the tile in the given column and row.
+pub fn tile_origin(support: ((f32, f32), (f32, f32)), column: usize, row: usize) -> (f64, f64) {
+    ((support.0).0 as f64 + column as f64, (support.1).0 as f64 + row as f64)
+}
+
+/// Returns the polynomial `q(u, v) = p(x + u, y + v)`.
+pub fn shift(p: &Polynomial, x: f64, y: f64) -> Polynomial {
+    let degree_y = p.iter().map(Vec::len).max().unwrap_or(0);
+    let mut result = vec![vec![0.; degree_y]; p.len()];
+    // expands `c (x + u)^m (y + v)^n` into powers of `u` and `v`
+    for (m, coefficients) in p.iter().enumerate() {
+        for (n, &c) in coefficients.iter().enumerate() {
+            for a in 0..m + 1 {
+                for b in 0..n + 1 {
+                    result[a][b] += c * binomial(m, a) * x.powi((m - a) as i32) *
+                        binomial(n, b) * y.powi((n - b) as i32);
+                }
+            }
+        }
+    }
+    result
+}
+
+/// A polynomial in several variables, mapping the powers of the variables to the coefficient.
+type Multinomial = BTreeMap<Vec<u8>, f64>;
+
+fn multiply(a: &Multinomial, b: &Multinomial) -> Multinomial {
+    let mut result = Multinomial::new();
+    for (powers_a, &c_a) in a {
+        for (powers_b, &c_b) in b {
+            let powers = powers_a.iter().zip(powers_b).map(|(p, q)| p + q).collect();
+            *result.entry(powers).or_insert(0.) += c_a * c_b;
+        }
+    }
+    result
+}
+
+/// Returns the single term `c v_0^p_0 v_1^p_1 ...`.
+fn term(c: f64, powers: Vec<u8>) -> Multinomial {
+    let mut result = Multinomial::new();
+    result.insert(powers, c);
+    result
+}
+
+/// Computes the polynomials of all tiles for curves of the given degree in the variables
+/// `[x0, y0, x1, y1, ...]`, where `(x(t), y(t)) = sum (x_k, y_k) t^k` for `t` from 0 to 1.
+///
+/// The tiles are given in tile coordinates. `index` converts the powers of the variables of each
+/// term.
+fn tile_polynomials<I, F>(
+    tiles: &[Vec<Polynomial>],
+    degree: usize,
+    index: F,
+) -> Vec<Vec<Vec<(f32, I)>>>
+where
+    F: Fn(&[u8]) -> I,
+{
+    // the coefficients of the curve followed by `t`
+    let variables = 2 * (degree + 1) + 1;
+    let t = variables - 1;
+    let monomial = |variable: usize, power: u8, c: f64| {
+        let mut powers = vec![0; variables];
+        powers[variable] = power;
+        term(c, powers)
+    };
+    let sum = |terms: Vec<Multinomial>| {
+        let mut result = Multinomial::new();
+        for term in terms {
+            for (powers, c) in term {
+                *result.entry(powers).or_insert(0.) += c;
+            }
+        }
+        result
+    };
+    let curve = |offset: usize| {
+        sum((0..degree + 1)
+            .map(|k| multiply(&monomial(2 * k + offset, 1, 1.), &monomial(t, k as u8, 1.)))
+            .collect())
+    };
+    let (x, y) = (curve(0), curve(1));
+    // `dy = y'(t) dt`
+    let dy = sum((1..degree + 1)
+        .map(|k| multiply(&monomial(2 * k + 1, 1, k as f64), &monomial(t, k as u8 - 1, 1.)))
+        .collect());
+
+    let max_m = tiles.iter().flat_map(|row| row.iter()).map(Vec::len).max().unwrap_or(0);
+    let max_n = tiles
+        .iter()
+        .flat_map(|row| row.iter())
+        .flat_map(|tile| tile.iter().map(Vec::len))
+        .max()
+        .unwrap_or(0);
+    let powers = |base: &Multinomial, count: usize| {
+        let mut powers = vec![term(1., vec![0; variables])];
+        for i in 1..count {
+            let next = multiply(&powers[i - 1], base);
+            powers.push(next);
+        }
+        powers
+    };
+    // `F` contains powers of `u` up to `max_m`, since it is an integral in `u`
+    let x_powers = powers(&x, max_m + 1);
+    let y_powers = powers(&y, max_n);
+
+    // the integral of `u^m v^n dy` along the curve for all `m` and `n`
+    let basis = x_powers
+        .iter()
+        .map(|x_power| {
+            y_powers
+                .iter()
+                .map(|y_power| {
+                    let mut result = Multinomial::new();
+                    for (mut powers, c) in multiply(&multiply(x_power, y_power), &dy) {
+                        let t = powers.pop().unwrap();
+                        *result.entry(powers).or_insert(0.) += c / (t as f64 + 1.);
+                    }
+                    result
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    tiles
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|tile| {
+                    let mut polynomial = Multinomial::new();
+                    for (m, coefficients) in tile.iter().enumerate() {
+                        for (n, &c) in coefficients.iter().enumerate() {
+                            // `u^m` integrates to `u^(m + 1) / (m + 1)`
+                            let c = c / (m + 1) as f64;
+                            for (powers, &b) in &basis[m + 1][n] {
+                                *polynomial.entry(powers.clone()).or_insert(0.) += c * b;
+                            }
+                        }
+                    }
+                    // drop terms that only remain due to rounding
+                    let max = polynomial.values().fold(0., |max: f64, c| max.max(c.abs()));
+                    polynomial
+                        .into_iter()
+                        .filter(|&(_, c)| c.abs() > 1.0e-12 * max)
+                        .map(|(powers, c)| (c as f32, index(&powers)))
+                        .collect()
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn binomial(n: usize, k: usize) -> f64 {
+    (0..k).fold(1., |result, i| result * (n - i) as f64 / (i + 1) as f64)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use filter::{BoxFilter, Evaluate};
+    use geometry::{Line, Point, QuadraticBezier, Rect};
+    use rasterizer;
+
+    const EPS: f32 = 1.0e-5;
+
+    /// The tent filter `(1 - |x|) (1 - |y|)`.
+    fn tent() -> PiecewisePolynomial {
+        PiecewisePolynomial {
+            name: "Tent".to_string(),
+            support: ((-1., 1.), (-1., 1.)),
+            pieces: vec![
+                vec![vec![vec![1., 1.], vec![1., 1.]], vec![vec![1., 1.], vec![-1., -1.]]],
+                vec![vec![vec![1., -1.], vec![1., -1.]], vec![vec![1., -1.], vec![-1., 1.]]],
+            ],
+        }
+    }
+
+    #[test]
+    fn test_box() {
+        let definition = PiecewisePolynomial {
+            name: "Box".to_string(),
+            support: ((-0.5, 0.5), (-0.5, 0.5)),
+            pieces: vec![vec![vec![vec![1.]]]],
+        };
+        let filter = definition.to_filter(true).unwrap();
+        let box_filter = BoxFilter::new(1., 1.);
+
+        let line = Line::new(Point::new(0.2, 0.9), Point::new(0.7, 0.1));
+        let (pv, acc) = filter.eval(line, (0, 0));
+        let (expected_pv, expected_acc) = box_filter.eval(line, (0, 0));
+        assert!((pv - expected_pv).abs() < EPS && (acc - expected_acc).abs() < EPS);
+
+        let curve = QuadraticBezier::new(line.start, Point::new(0.9, 0.8), line.end);
+        let (pv, acc) = filter.eval(curve, (0, 0));
+        let (expected_pv, expected_acc) = box_filter.eval(curve, (0, 0));
+        assert!((pv - expected_pv).abs() < EPS && (acc - expected_acc).abs() < EPS);
+    }
+
+    #[test]
+    fn test_tiles() {
+        let filter = tent().to_filter(true).unwrap();
+        // the right edge of the top left tile covers all of it, where the filter is `u v`
+        let line = Line::new(Point::new(1., 0.), Point::new(1., 1.));
+        let (pv, acc) = filter.eval(line, (0, 0));
+        assert!((pv - 0.25).abs() < EPS && (acc - 0.25).abs() < EPS);
+        // the filter is `(1 - u) v` in the top right tile
+        let line = Line::new(Point::new(0.5, 0.), Point::new(0.5, 1.));
+        let (pv, acc) = filter.eval(line, (1, 0));
+        assert!((pv - 0.1875).abs() < EPS && (acc - 0.25).abs() < EPS);
+
+        // quadratic tiles agree with line tiles on straight curves
+        let curve = QuadraticBezier::new(line.start, Point::new(0.5, 0.5), line.end);
+        let (curve_pv, curve_acc) = filter.eval(curve, (1, 0));
+        assert!((curve_pv - pv).abs() < EPS && (curve_acc - acc).abs() < EPS);
+
+        let mut invalid = tent();
+        invalid.support = ((-1., 1.5), (-1., 1.));
+        assert_eq!(invalid.to_filter(false).unwrap_err(), DefinitionError::Support);
+        invalid.support = ((-1., 2.), (-1., 1.));
+        assert_eq!(invalid.to_filter(false).unwrap_err(), DefinitionError::Pieces);
+    }
+
+    #[test]
+    fn test_rasterize() {
+        // `1` left of the center and `x` right of it, which tells the sides apart
+        let definition = PiecewisePolynomial {
+            name: "Ramp".to_string(),
+            support: ((-1., 1.), (-0.5, 0.5)),
+            pieces: vec![vec![vec![vec![1.]], vec![vec![0.], vec![1.]]]],
+        };
+        let filter = definition.to_filter(false).unwrap();
+        let corners = [
+            Point::new(0., 0.),
+            Point::new(2.5, 0.),
+            Point::new(2.5, 4.),
+            Point::new(0., 4.),
+        ];
+        let curves = (0..4)
+            .map(|i| Line::new(corners[i], corners[(i + 1) % 4]))
+            .collect::<Vec<_>>();
+        let mut buffer = Vec::new();
+        rasterizer::rasterize_parallel(Rect::new(0., 0., 4., 4.), &filter, &curves, &mut buffer);
+        // the pixel centered at 2.5 only covers the side left of its center
+        assert!((buffer[4 + 1].abs() - 1.).abs() < EPS);
+        assert!((buffer[4 + 2].abs() - 2. / 3.).abs() < EPS);
+
+        let json = DynamicFilter::from_slice(&filter.to_json()).unwrap();
+        let packed = DynamicFilter::from_slice(&filter.to_message_pack()).unwrap();
+        let line = Line::new(Point::new(0.2, 0.9), Point::new(0.7, 0.1));
+        assert_eq!(json.eval(line, (1, 0)), filter.eval(line, (1, 0)));
+        assert_eq!(packed.eval(line, (1, 0)), filter.eval(line, (1, 0)));
+    }
+}
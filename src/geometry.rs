@@ -1,3 +1,5 @@
+use arrayvec::ArrayVec;
+
 use std::ops::{Add, Sub, Mul, Div, Neg};
 use std::convert::From;
 
@@ -64,6 +66,10 @@ impl Vec2d {
     pub fn norm(self) -> f32 {
         (self.x * self.x + self.y * self.y).sqrt()
     }
+
+    pub fn dot(self, other: Vec2d) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
 }
 
 impl Add<Vec2d> for Vec2d {
@@ -136,6 +142,110 @@ impl Div<f32> for Vec2d {
     }
 }
 
+/// An affine transformation mapping `(x, y)` to `(a x + c y + e, b x + d y + f)`.
+///
+/// The coefficients are named like those of the SVG `matrix(a b c d e f)` transform.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Transform {
+    pub a: f32,
+    pub b: f32,
+    pub c: f32,
+    pub d: f32,
+    pub e: f32,
+    pub f: f32,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn new(a: f32, b: f32, c: f32, d: f32, e: f32, f: f32) -> Transform {
+        Transform {
+            a: a,
+            b: b,
+            c: c,
+            d: d,
+            e: e,
+            f: f,
+        }
+    }
+
+    pub fn identity() -> Transform {
+        Transform::new(1., 0., 0., 1., 0., 0.)
+    }
+
+    pub fn translate(tx: f32, ty: f32) -> Transform {
+        Transform::new(1., 0., 0., 1., tx, ty)
+    }
+
+    pub fn scale(sx: f32, sy: f32) -> Transform {
+        Transform::new(sx, 0., 0., sy, 0., 0.)
+    }
+
+    /// A rotation about the origin by `angle` (in radians).
+    pub fn rotate(angle: f32) -> Transform {
+        let (sin, cos) = angle.sin_cos();
+        Transform::new(cos, sin, -sin, cos, 0., 0.)
+    }
+
+    /// A skew along the x axis by `angle` (in radians).
+    pub fn skew_x(angle: f32) -> Transform {
+        Transform::new(1., 0., angle.tan(), 1., 0., 0.)
+    }
+
+    /// A skew along the y axis by `angle` (in radians).
+    pub fn skew_y(angle: f32) -> Transform {
+        Transform::new(1., angle.tan(), 0., 1., 0., 0.)
+    }
+
+    pub fn determinant(&self) -> f32 {
+        self.a * self.d - self.b * self.c
+    }
+
+    /// Returns `None` if the transformation is singular.
+    pub fn inverse(&self) -> Option<Transform> {
+        let det = self.determinant();
+        if det == 0. || !det.is_finite() {
+            return None;
+        }
+        let a = self.d / det;
+        let b = -self.b / det;
+        let c = -self.c / det;
+        let d = self.a / det;
+        Some(Transform::new(a, b, c, d, -(a * self.e + c * self.f), -(b * self.e + d * self.f)))
+    }
+
+    pub fn apply(&self, point: Point) -> Point {
+        Point::new(
+            self.a * point.x + self.c * point.y + self.e,
+            self.b * point.x + self.d * point.y + self.f,
+        )
+    }
+
+    /// Transforms a displacement, i.e. ignores the translation part.
+    pub fn apply_vec(&self, vec: Vec2d) -> Vec2d {
+        Vec2d::new(self.a * vec.x + self.c * vec.y, self.b * vec.x + self.d * vec.y)
+    }
+}
+
+/// `lhs * rhs` is the transformation that first applies `rhs` and then `lhs`.
+impl Mul<Transform> for Transform {
+    type Output = Transform;
+    fn mul(self, rhs: Transform) -> Transform {
+        Transform {
+            a: self.a * rhs.a + self.c * rhs.b,
+            b: self.b * rhs.a + self.d * rhs.b,
+            c: self.a * rhs.c + self.c * rhs.d,
+            d: self.b * rhs.c + self.d * rhs.d,
+            e: self.a * rhs.e + self.c * rhs.f + self.e,
+            f: self.b * rhs.e + self.d * rhs.f + self.f,
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Default)]
 pub struct Line {
     pub start: Point,
@@ -166,6 +276,542 @@ impl QuadraticBezier {
             end: end,
         }
     }
+
+    /// Evaluates the curve at the parameter `t`.
+    pub fn eval(&self, t: f32) -> Point {
+        self.blossom(t, t)
+    }
+
+    /// Returns the part of the curve between the parameters `t0` and `t1`.
+    ///
+    /// The returned curve is parametrized from 0 to 1 and traces exactly the same points as
+    /// `self` does between `t0` and `t1`.
+    pub fn subcurve(&self, t0: f32, t1: f32) -> QuadraticBezier {
+        QuadraticBezier {
+            start: self.blossom(t0, t0),
+            control: self.blossom(t0, t1),
+            end: self.blossom(t1, t1),
+        }
+    }
+
+    /// Returns the parameters in `(0, 1)` where the curve has a horizontal or vertical tangent.
+    pub fn extrema(&self) -> ArrayVec<[f32; 2]> {
+        let mut result = ArrayVec::new();
+        let denom = self.start - self.control * 2. + self.end.vec_from_origin();
+        let numer = self.start - self.control;
+        for &(n, d) in &[(numer.x, denom.x), (numer.y, denom.y)] {
+            if d != 0. {
+                let t = n / d;
+                if t > 0. && t < 1. {
+                    result.push(t);
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns the coefficients of the curve in power basis, i.e. the curve is
+    /// `poly[0] + poly[1] * t + poly[2] * t^2`.
+    pub fn polynomial(&self) -> [Vec2d; 3] {
+        [
+            self.start.vec_from_origin(),
+            (self.control - self.start) * 2.,
+            (self.start - self.control) + (self.end - self.control),
+        ]
+    }
+
+    /// Returns the same curve as a cubic curve.
+    pub fn to_cubic(&self) -> CubicBezier {
+        CubicBezier::new(
+            self.start,
+            self.start + (self.control - self.start) * (2. / 3.),
+            self.end + (self.control - self.end) * (2. / 3.),
+            self.end,
+        )
+    }
+
+    /// Approximates the curve by lines that are nowhere further away from the curve than
+    /// `tolerance`.
+    pub fn flatten(&self, tolerance: f32) -> FlattenQuadratic {
+        // The distance between a quadratic curve and its chord is at most |p0 - 2 p1 + p2| / 4
+        // and it shrinks quadratically with the number of uniform subdivisions.
+        let deviation = ((self.start - self.control) + (self.end - self.control)).norm() / 4.;
+        let steps = (deviation / tolerance).sqrt().ceil().max(1.);
+        FlattenQuadratic {
+            curve: *self,
+            steps: steps as u32,
+            step: 0,
+            last: self.start,
+        }
+    }
+
+    /// The polar form of the curve. `blossom(t, t)` is the point at the parameter `t`.
+    fn blossom(&self, u: f32, v: f32) -> Point {
+        let w0 = (1. - u) * (1. - v);
+        let w1 = (1. - u) * v + u * (1. - v);
+        let w2 = u * v;
+        Point::new(
+            w0 * self.start.x + w1 * self.control.x + w2 * self.end.x,
+            w0 * self.start.y + w1 * self.control.y + w2 * self.end.y,
+        )
+    }
+}
+
+/// Iterator over the lines approximating a quadratic curve, see `QuadraticBezier::flatten`.
+#[derive(Debug, Clone)]
+pub struct FlattenQuadratic {
+    curve: QuadraticBezier,
+    steps: u32,
+    step: u32,
+    last: Point,
+}
+
+impl Iterator for FlattenQuadratic {
+    type Item = Line;
+
+    fn next(&mut self) -> Option<Line> {
+        if self.step >= self.steps {
+            return None;
+        }
+        self.step += 1;
+        let next = if self.step == self.steps {
+            self.curve.end
+        } else {
+            self.curve.eval(self.step as f32 / self.steps as f32)
+        };
+        let line = Line::new(self.last, next);
+        self.last = next;
+        Some(line)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub struct CubicBezier {
+    pub start: Point,
+    pub control1: Point,
+    pub control2: Point,
+    pub end: Point,
+}
+
+impl CubicBezier {
+    pub fn new(start: Point, control1: Point, control2: Point, end: Point) -> CubicBezier {
+        CubicBezier {
+            start: start,
+            control1: control1,
+            control2: control2,
+            end: end,
+        }
+    }
+
+    /// Evaluates the curve at the parameter `t`.
+    pub fn eval(&self, t: f32) -> Point {
+        self.blossom(t, t, t)
+    }
+
+    /// Returns the part of the curve between the parameters `t0` and `t1`.
+    ///
+    /// The returned curve is parametrized from 0 to 1 and traces exactly the same points as
+    /// `self` does between `t0` and `t1`.
+    pub fn subcurve(&self, t0: f32, t1: f32) -> CubicBezier {
+        CubicBezier {
+            start: self.blossom(t0, t0, t0),
+            control1: self.blossom(t0, t0, t1),
+            control2: self.blossom(t0, t1, t1),
+            end: self.blossom(t1, t1, t1),
+        }
+    }
+
+    /// Returns the parameters in `(0, 1)` where the curve has a horizontal or vertical tangent.
+    pub fn extrema(&self) -> ArrayVec<[f32; 4]> {
+        let mut result = ArrayVec::new();
+        // the derivative is a quadratic curve with these control points (up to a factor of 3)
+        let d0 = self.control1 - self.start;
+        let d1 = self.control2 - self.control1;
+        let d2 = self.end - self.control2;
+        let a = d0 - d1 * 2. + d2;
+        let b = (d1 - d0) * 2.;
+        for &(a, b, c) in &[(a.x, b.x, d0.x), (a.y, b.y, d0.y)] {
+            for t in solve_quadratic(a, b, c) {
+                if t > 0. && t < 1. {
+                    result.push(t);
+                }
+            }
+        }
+        result
+    }
+
+    /// Returns the coefficients of the curve in power basis, i.e. the curve is
+    /// `poly[0] + poly[1] * t + poly[2] * t^2 + poly[3] * t^3`.
+    pub fn polynomial(&self) -> [Vec2d; 4] {
+        let d0 = self.control1 - self.start;
+        let d1 = self.control2 - self.control1;
+        let d2 = self.end - self.control2;
+        [
+            self.start.vec_from_origin(),
+            d0 * 3.,
+            (d1 - d0) * 3.,
+            d2 - d1 * 2. + d0,
+        ]
+    }
+
+    /// Returns the derivative of the curve at the parameter `t`.
+    pub fn derivative(&self, t: f32) -> Vec2d {
+        let poly = self.polynomial();
+        poly[1] + poly[2] * (2. * t) + poly[3] * (3. * t * t)
+    }
+
+    /// Approximates the curve by quadratic curves that are nowhere further away from the curve
+    /// than `tolerance`.
+    pub fn to_quadratics(&self, tolerance: f32) -> CubicToQuadratics {
+        // A single quadratic curve deviates at most sqrt(3) / 36 * |p3 - 3 p2 + 3 p1 - p0| from
+        // the cubic and the error shrinks cubically with the number of uniform subdivisions.
+        let deviation = self.polynomial()[3].norm() * 3f32.sqrt() / 36.;
+        let steps = (deviation / tolerance).cbrt().ceil().max(1.);
+        CubicToQuadratics {
+            curve: *self,
+            steps: steps as u32,
+            step: 0,
+        }
+    }
+
+    /// The polar form of the curve. `blossom(t, t, t)` is the point at the parameter `t`.
+    fn blossom(&self, u: f32, v: f32, w: f32) -> Point {
+        let lerp = |a: Point, b: Point, t: f32| a + (b - a) * t;
+        let p01 = lerp(self.start, self.control1, u);
+        let p12 = lerp(self.control1, self.control2, u);
+        let p23 = lerp(self.control2, self.end, u);
+        let p012 = lerp(p01, p12, v);
+        let p123 = lerp(p12, p23, v);
+        lerp(p012, p123, w)
+    }
+}
+
+/// Iterator over the quadratic curves approximating a cubic curve, see
+/// `CubicBezier::to_quadratics`.
+#[derive(Debug, Clone)]
+pub struct CubicToQuadratics {
+    curve: CubicBezier,
+    steps: u32,
+    step: u32,
+}
+
+impl Iterator for CubicToQuadratics {
+    type Item = QuadraticBezier;
+
+    fn next(&mut self) -> Option<QuadraticBezier> {
+        if self.step >= self.steps {
+            return None;
+        }
+        let t0 = self.step as f32 / self.steps as f32;
+        self.step += 1;
+        let t1 = self.step as f32 / self.steps as f32;
+        let piece = self.curve.subcurve(t0, t1);
+
+        // the mean of the control points that match the tangent at the start and at the end
+        let control = piece.start +
+            ((piece.control1 - piece.start) * 3. + (piece.control2 - piece.start) * 3. +
+                 (piece.start - piece.end)) / 4.;
+        Some(QuadraticBezier::new(piece.start, control, piece.end))
+    }
+}
+
+/// One piece of an outline.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Segment {
+    Line(Line),
+    Quadratic(QuadraticBezier),
+    Cubic(CubicBezier),
+}
+
+impl Segment {
+    pub fn start(&self) -> Point {
+        match *self {
+            Segment::Line(ref line) => line.start,
+            Segment::Quadratic(ref curve) => curve.start,
+            Segment::Cubic(ref curve) => curve.start,
+        }
+    }
+
+    pub fn end(&self) -> Point {
+        match *self {
+            Segment::Line(ref line) => line.end,
+            Segment::Quadratic(ref curve) => curve.end,
+            Segment::Cubic(ref curve) => curve.end,
+        }
+    }
+
+    /// Evaluates the segment at the parameter `t`.
+    pub fn eval(&self, t: f32) -> Point {
+        match *self {
+            Segment::Line(ref line) => line.start + (line.end - line.start) * t,
+            Segment::Quadratic(ref curve) => curve.eval(t),
+            Segment::Cubic(ref curve) => curve.eval(t),
+        }
+    }
+
+    /// Returns the part of the segment between the parameters `t0` and `t1`.
+    pub fn subsegment(&self, t0: f32, t1: f32) -> Segment {
+        match *self {
+            Segment::Line(ref line) => {
+                let direction = line.end - line.start;
+                Segment::Line(Line::new(line.start + direction * t0, line.start + direction * t1))
+            }
+            Segment::Quadratic(ref curve) => Segment::Quadratic(curve.subcurve(t0, t1)),
+            Segment::Cubic(ref curve) => Segment::Cubic(curve.subcurve(t0, t1)),
+        }
+    }
+
+    /// Returns the segment traversed in the opposite direction.
+    pub fn reverse(&self) -> Segment {
+        match *self {
+            Segment::Line(ref line) => Segment::Line(Line::new(line.end, line.start)),
+            Segment::Quadratic(ref curve) => {
+                Segment::Quadratic(QuadraticBezier::new(curve.end, curve.control, curve.start))
+            }
+            Segment::Cubic(ref curve) => {
+                Segment::Cubic(CubicBezier::new(curve.end,
+                                                curve.control2,
+                                                curve.control1,
+                                                curve.start))
+            }
+        }
+    }
+
+    /// The direction in which the segment leaves its start point, or `None` if all of its
+    /// points coincide. The returned vector is not normalized.
+    pub fn start_tangent(&self) -> Option<Vec2d> {
+        let start = self.start();
+        let directions = match *self {
+            Segment::Line(ref line) => [line.end - start, Vec2d::default(), Vec2d::default()],
+            Segment::Quadratic(ref curve) => {
+                [curve.control - start, curve.end - start, Vec2d::default()]
+            }
+            Segment::Cubic(ref curve) => {
+                [curve.control1 - start, curve.control2 - start, curve.end - start]
+            }
+        };
+        directions.iter().cloned().find(|&direction| direction != Vec2d::default())
+    }
+
+    /// The direction in which the segment arrives at its end point, or `None` if all of its
+    /// points coincide. The returned vector is not normalized.
+    pub fn end_tangent(&self) -> Option<Vec2d> {
+        self.reverse().start_tangent().map(|direction| -direction)
+    }
+
+    /// Bézier curves are invariant under affine maps, so it suffices to transform the control
+    /// points.
+    pub fn transform(&self, transform: &Transform) -> Segment {
+        let t = |point| transform.apply(point);
+        match *self {
+            Segment::Line(ref line) => Segment::Line(Line::new(t(line.start), t(line.end))),
+            Segment::Quadratic(ref curve) => {
+                Segment::Quadratic(QuadraticBezier::new(t(curve.start),
+                                                        t(curve.control),
+                                                        t(curve.end)))
+            }
+            Segment::Cubic(ref curve) => {
+                Segment::Cubic(CubicBezier::new(t(curve.start),
+                                                t(curve.control1),
+                                                t(curve.control2),
+                                                t(curve.end)))
+            }
+        }
+    }
+}
+
+impl From<Line> for Segment {
+    fn from(line: Line) -> Segment {
+        Segment::Line(line)
+    }
+}
+
+impl From<QuadraticBezier> for Segment {
+    fn from(curve: QuadraticBezier) -> Segment {
+        Segment::Quadratic(curve)
+    }
+}
+
+impl From<CubicBezier> for Segment {
+    fn from(curve: CubicBezier) -> Segment {
+        Segment::Cubic(curve)
+    }
+}
+
+/// A section of an ellipse whose x axis is rotated by `x_rotation` (in radians).
+///
+/// The arc starts at `start_angle` and runs through `sweep_angle`, where a positive sweep goes
+/// from the positive x axis towards the positive y axis.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct EllipticalArc {
+    pub center: Point,
+    pub radii: Vec2d,
+    pub x_rotation: f32,
+    pub start_angle: f32,
+    pub sweep_angle: f32,
+}
+
+impl EllipticalArc {
+    /// Returns the point on the ellipse at `angle`.
+    pub fn eval(&self, angle: f32) -> Point {
+        let (sin_phi, cos_phi) = self.x_rotation.sin_cos();
+        let (sin, cos) = angle.sin_cos();
+        let x = self.radii.x * cos;
+        let y = self.radii.y * sin;
+        self.center + Vec2d::new(cos_phi * x - sin_phi * y, sin_phi * x + cos_phi * y)
+    }
+
+    /// Returns the derivative of `eval` at `angle`.
+    fn tangent(&self, angle: f32) -> Vec2d {
+        let (sin_phi, cos_phi) = self.x_rotation.sin_cos();
+        let (sin, cos) = angle.sin_cos();
+        let x = -self.radii.x * sin;
+        let y = self.radii.y * cos;
+        Vec2d::new(cos_phi * x - sin_phi * y, sin_phi * x + cos_phi * y)
+    }
+
+    /// Approximates the arc by cubic curves that span at most a quarter of the ellipse each.
+    pub fn to_cubics(&self) -> Vec<CubicBezier> {
+        use std::f32::consts::FRAC_PI_2;
+
+        let count = (self.sweep_angle.abs() / FRAC_PI_2).ceil().max(1.) as usize;
+        let step = self.sweep_angle / count as f32;
+        // length of the control vectors relative to the tangent for the best circular fit
+        let k = 4. / 3. * (step / 4.).tan();
+
+        let mut curves = (0..count)
+            .map(|i| {
+                let angle0 = self.start_angle + step * i as f32;
+                let angle1 = angle0 + step;
+                let start = self.eval(angle0);
+                let end = self.eval(angle1);
+                CubicBezier::new(
+                    start,
+                    start + self.tangent(angle0) * k,
+                    end - self.tangent(angle1) * k,
+                    end,
+                )
+            })
+            .collect::<Vec<_>>();
+
+        // make sure that full ellipses are closed despite rounding errors
+        if self.sweep_angle.abs() >= 2. * ::std::f32::consts::PI {
+            let start = curves[0].start;
+            curves[count - 1].end = start;
+        }
+        curves
+    }
+}
+
+/// A connected sequence of segments.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subpath {
+    pub segments: Vec<Segment>,
+    /// Whether the subpath was closed explicitly, which makes a difference for strokes only.
+    /// A closed subpath ends at its start point.
+    pub closed: bool,
+}
+
+impl Subpath {
+    /// Returns the segments together with the line that closes the subpath, as it is needed
+    /// for filling.
+    pub fn into_closed_segments(mut self) -> Vec<Segment> {
+        if let (Some(first), Some(last)) = (self.segments.first().cloned(),
+                                            self.segments.last().cloned()) {
+            if first.start() != last.end() {
+                self.segments.push(Line::new(last.end(), first.start()).into());
+            }
+        }
+        self.segments
+    }
+}
+
+/// Collects subpaths from a sequence of drawing commands.
+#[derive(Debug, Clone, Default)]
+pub struct PathBuilder {
+    subpaths: Vec<Subpath>,
+    /// The segments of the current subpath.
+    segments: Vec<Segment>,
+    start: Point,
+    current: Point,
+}
+
+impl PathBuilder {
+    pub fn new() -> PathBuilder {
+        PathBuilder::default()
+    }
+
+    /// The end point of the last drawing command.
+    pub fn current_point(&self) -> Point {
+        self.current
+    }
+
+    /// Starts a new subpath at `pt`.
+    pub fn move_to(&mut self, pt: Point) {
+        self.finish_subpath(false);
+        self.start = pt;
+        self.current = pt;
+    }
+
+    /// Continues the subpath with a line to `pt`. A line without length is only kept if it is
+    /// the first segment, since a subpath of zero length still gets caps when stroked.
+    pub fn line_to(&mut self, pt: Point) {
+        if pt != self.current || self.segments.is_empty() {
+            self.segments.push(Line::new(self.current, pt).into());
+        }
+        self.current = pt;
+    }
+
+    pub fn quadratic_to(&mut self, control: Point, pt: Point) {
+        self.segments.push(
+            QuadraticBezier::new(self.current, control, pt).into(),
+        );
+        self.current = pt;
+    }
+
+    pub fn cubic_to(&mut self, control1: Point, control2: Point, pt: Point) {
+        self.segments.push(
+            CubicBezier::new(self.current, control1, control2, pt).into(),
+        );
+        self.current = pt;
+    }
+
+    /// Continues the subpath with an arc. The arc is expected to start at the current point.
+    pub fn arc(&mut self, arc: &EllipticalArc) {
+        for curve in arc.to_cubics() {
+            self.cubic_to(curve.control1, curve.control2, curve.end);
+        }
+    }
+
+    /// Closes the current subpath with a line back to its start. Drawing commands that follow
+    /// start a new subpath at the same point.
+    pub fn close(&mut self) {
+        let start = self.start;
+        self.line_to(start);
+        self.finish_subpath(true);
+    }
+
+    fn finish_subpath(&mut self, closed: bool) {
+        if !self.segments.is_empty() {
+            self.subpaths.push(Subpath {
+                segments: ::std::mem::replace(&mut self.segments, Vec::new()),
+                closed: closed,
+            });
+        }
+    }
+
+    pub fn into_subpaths(mut self) -> Vec<Subpath> {
+        self.finish_subpath(false);
+        self.subpaths
+    }
+
+    /// Returns the segments of all subpaths, each of them closed with a straight line.
+    pub fn into_segments(self) -> Vec<Segment> {
+        self.into_subpaths()
+            .into_iter()
+            .flat_map(Subpath::into_closed_segments)
+            .collect()
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq)]
@@ -191,6 +837,41 @@ impl Rect {
         }
     }
 
+    /// Returns the smallest rect that contains all of the given points.
+    pub fn enclosing<I: IntoIterator<Item = Point>>(points: I) -> Rect {
+        let mut iter = points.into_iter();
+        let first = iter.next().unwrap_or_default();
+        let (min, max) = iter.fold((first, first), |(min, max), pt| {
+            (
+                Point::new(min.x.min(pt.x), min.y.min(pt.y)),
+                Point::new(max.x.max(pt.x), max.y.max(pt.y)),
+            )
+        });
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+
+    /// Returns the smallest rect that contains both rects.
+    pub fn union(self, other: Rect) -> Rect {
+        let corners = [
+            self.top_left(),
+            self.bottom_right(),
+            other.top_left(),
+            other.bottom_right(),
+        ];
+        Rect::enclosing(corners.iter().cloned())
+    }
+
+    /// Returns the bounding box of the rect after it has been transformed with `transform`.
+    pub fn transform(self, transform: &Transform) -> Rect {
+        let corners = [
+            self.top_left(),
+            self.top_right(),
+            self.bottom_left(),
+            self.bottom_right(),
+        ];
+        Rect::enclosing(corners.iter().map(|&corner| transform.apply(corner)))
+    }
+
     /// Normalizes a rect to contain no negative width/height.
     ///
     /// A normalized rect always has its origin at the top left.
@@ -280,4 +961,73 @@ impl From<Size> for ImageSize {
             height: size.height as usize,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Returns the real roots of `a * t^2 + b * t + c`.
+pub fn solve_quadratic(a: f32, b: f32, c: f32) -> ArrayVec<[f32; 2]> {
+    let mut roots = ArrayVec::new();
+    if a.abs() <= ::std::f32::EPSILON * (b.abs() + c.abs()) {
+        if b != 0. {
+            roots.push(-c / b);
+        }
+        return roots;
+    }
+
+    let discriminant = b * b - 4. * a * c;
+    if discriminant < 0. {
+        return roots;
+    }
+    // numerically stable form that avoids cancellation between `b` and the square root
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    roots.push(q / a);
+    if q != 0. {
+        roots.push(c / q);
+    }
+    roots
+}
+
+/// Returns the roots of `a * t^3 + b * t^2 + c * t + d` that lie in the interval `[0, 1]`.
+///
+/// The interval is split into pieces where the polynomial is monotonic, each of which contains
+/// at most one root that is then found by bisection.
+pub fn cubic_roots_in_unit_interval(a: f32, b: f32, c: f32, d: f32) -> ArrayVec<[f32; 3]> {
+    let poly = |t: f32| ((a * t + b) * t + c) * t + d;
+
+    let mut bounds: ArrayVec<[f32; 4]> = ArrayVec::new();
+    bounds.push(0.);
+    // the comparisons also drop NaN roots of degenerate coefficients before sorting
+    let mut critical = solve_quadratic(3. * a, 2. * b, c);
+    critical.retain(|&mut t| t > 0. && t < 1.);
+    critical.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    bounds.extend(critical);
+    bounds.push(1.);
+
+    let mut roots = ArrayVec::new();
+    for window in bounds.windows(2) {
+        let (mut lo, mut hi) = (window[0], window[1]);
+        let (f_lo, f_hi) = (poly(lo), poly(hi));
+        if f_lo == 0. {
+            if roots.last() != Some(&lo) {
+                roots.push(lo);
+            }
+            continue;
+        }
+        if f_hi == 0. {
+            roots.push(hi);
+            continue;
+        }
+        if f_lo.signum() == f_hi.signum() {
+            continue;
+        }
+        for _ in 0..32 {
+            let mid = 0.5 * (lo + hi);
+            if poly(mid).signum() == f_lo.signum() {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        roots.push(0.5 * (lo + hi));
+    }
+    roots
+}
@@ -0,0 +1,291 @@
+use geometry::{Point, Vec2d, Transform};
+use render::PreRgba;
+
+/// How a gradient continues beyond its first and last stop.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SpreadMethod {
+    /// Uses the color of the nearest end.
+    Pad,
+    /// Mirrors the gradient at its ends.
+    Reflect,
+    /// Starts over at the other end.
+    Repeat,
+}
+
+impl Default for SpreadMethod {
+    fn default() -> SpreadMethod {
+        SpreadMethod::Pad
+    }
+}
+
+/// Maps a gradient parameter to a color.
+///
+/// The colors are interpolated linearly in linear RGB with premultiplied alpha.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    /// Offsets in ascending order and their colors. There has to be at least one stop.
+    pub stops: Vec<(f32, PreRgba)>,
+    pub spread: SpreadMethod,
+}
+
+impl Gradient {
+    pub fn color_at(&self, t: f32) -> PreRgba {
+        let t = match self.spread {
+            SpreadMethod::Pad => t,
+            SpreadMethod::Repeat => t - t.floor(),
+            SpreadMethod::Reflect => {
+                let t = t.abs() % 2.;
+                if t > 1. { 2. - t } else { t }
+            }
+        };
+
+        // index of the first stop behind `t`
+        let index = match self.stops.iter().position(|&(offset, _)| offset > t) {
+            Some(index) => index,
+            None => return self.stops[self.stops.len() - 1].1,
+        };
+        if index == 0 {
+            return self.stops[0].1;
+        }
+        let (offset0, color0) = self.stops[index - 1];
+        let (offset1, color1) = self.stops[index];
+        let s = (t - offset0) / (offset1 - offset0);
+        color0 * (1. - s) + color1 * s
+    }
+
+    /// Splits the parameters from `t0` to `t1` into intervals on which the color is an affine
+    /// function of the parameter.
+    ///
+    /// Returns the bounds of the intervals in ascending order together with the colors at the
+    /// bounds, as limits from inside of the interval. Returns `None` if there are more than
+    /// `limit` intervals.
+    pub fn pieces(
+        &self,
+        t0: f32,
+        t1: f32,
+        limit: usize,
+    ) -> Option<Vec<(f32, f32, PreRgba, PreRgba)>> {
+        if !(t0 < t1) {
+            let color = self.color_at(t0);
+            return Some(vec![(t0, t1, color, color)]);
+        }
+        // every whole number starts another period
+        if self.spread != SpreadMethod::Pad && t1 - t0 > limit as f32 {
+            return None;
+        }
+
+        let offsets = self.stops.iter().map(|&(offset, _)| offset).collect::<Vec<_>>();
+        let mut splits = Vec::new();
+        match self.spread {
+            SpreadMethod::Pad => splits.extend(offsets),
+            SpreadMethod::Repeat => {
+                for period in t0.floor() as i64..t1.ceil() as i64 + 1 {
+                    let start = period as f32;
+                    splits.push(start);
+                    splits.extend(offsets.iter().map(|&offset| start + offset));
+                }
+            }
+            SpreadMethod::Reflect => {
+                for period in (t0 / 2.).floor() as i64..(t1 / 2.).ceil() as i64 + 1 {
+                    let center = 2. * period as f32;
+                    splits.push(center);
+                    splits.push(center + 1.);
+                    splits.extend(offsets.iter().map(|&offset| center + offset));
+                    splits.extend(offsets.iter().map(|&offset| center - offset));
+                }
+            }
+        }
+        splits.retain(|split| split.is_finite());
+        splits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mut bounds = vec![t0];
+        for split in splits {
+            // coinciding stops merely mark a jump of the color
+            if split > bounds[bounds.len() - 1] + 1.0e-6 && split < t1 - 1.0e-6 {
+                bounds.push(split);
+            }
+        }
+        bounds.push(t1);
+        if bounds.len() - 1 > limit {
+            return None;
+        }
+
+        Some(
+            bounds
+                .windows(2)
+                .map(|bounds| {
+                    let (start, end) = (bounds[0], bounds[1]);
+                    // the colors at the bounds are extrapolated from inside of the interval
+                    let quarter = 0.25 * (end - start);
+                    let (color0, color1) = (
+                        self.color_at(start + quarter),
+                        self.color_at(end - quarter),
+                    );
+                    let slope = (color1 - color0) * 0.5;
+                    (start, end, color0 - slope, color1 + slope)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// A gradient along the line from `start` to `end`.
+///
+/// `start` and `end` are given in the gradient space which `transform` maps to the space of the
+/// painted path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinearGradient {
+    pub gradient: Gradient,
+    pub start: Point,
+    pub end: Point,
+    pub transform: Transform,
+}
+
+impl LinearGradient {
+    /// Returns the gradient parameter as the affine function `t(p) = direction · p + offset` of
+    /// the point `p` in the space of the painted path.
+    ///
+    /// Returns `None` if the gradient vector or the transformation is degenerate.
+    pub fn parameter(&self) -> Option<(Vec2d, f32)> {
+        let inverse = match self.transform.inverse() {
+            Some(inverse) => inverse,
+            None => return None,
+        };
+        let delta = self.end - self.start;
+        let norm_squared = delta.x * delta.x + delta.y * delta.y;
+        if norm_squared == 0. {
+            return None;
+        }
+        let delta = delta / norm_squared;
+        // `inverse` maps `p` to `A p + b`, so `t(p) = (A^T delta) · p + (b - start) · delta`
+        let direction = Vec2d::new(
+            inverse.a * delta.x + inverse.b * delta.y,
+            inverse.c * delta.x + inverse.d * delta.y,
+        );
+        let offset = (inverse.e - self.start.x) * delta.x + (inverse.f - self.start.y) * delta.y;
+        Some((direction, offset))
+    }
+}
+
+/// A gradient between the focal point (`t = 0`) and the circle around `center` (`t = 1`).
+///
+/// All coordinates are given in the gradient space which `transform` maps to the space of the
+/// painted path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RadialGradient {
+    pub gradient: Gradient,
+    pub center: Point,
+    pub radius: f32,
+    /// Has to lie inside of the circle.
+    pub focal: Point,
+    pub transform: Transform,
+}
+
+impl RadialGradient {
+    /// Returns the gradient parameter at `point` given in gradient space.
+    ///
+    /// This is the `t` for which `point` lies on the circle around `focal + t (center - focal)`
+    /// with radius `t radius`.
+    pub fn parameter_at(&self, point: Point) -> f32 {
+        let d = self.center - self.focal;
+        let e = point - self.focal;
+        let e_dot_d = e.x * d.x + e.y * d.y;
+        let e_squared = e.x * e.x + e.y * e.y;
+        // since the focal point lies inside of the circle `a` is negative and there is exactly
+        // one non-negative root of `a t^2 - 2 (e · d) t + e · e`
+        let a = d.x * d.x + d.y * d.y - self.radius * self.radius;
+        if a >= 0. {
+            return 0.;
+        }
+        let discriminant = e_dot_d * e_dot_d - a * e_squared;
+        (e_dot_d - discriminant.max(0.).sqrt()) / a
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use palette::Rgba;
+
+    const EPS: f32 = 1.0e-5;
+
+    fn gradient(spread: SpreadMethod) -> Gradient {
+        Gradient {
+            stops: vec![
+                (0.25, PreRgba::from(Rgba::new(1., 0., 0., 1.))),
+                (0.75, PreRgba::from(Rgba::new(0., 0., 1., 0.5))),
+            ],
+            spread: spread,
+        }
+    }
+
+    #[test]
+    fn test_spread() {
+        let pad = gradient(SpreadMethod::Pad);
+        assert_eq!(pad.color_at(-1.).red, 1.);
+        assert_eq!(pad.color_at(2.).blue, 0.5);
+        let mid = pad.color_at(0.5);
+        assert!((mid.red - 0.5).abs() < EPS && (mid.blue - 0.25).abs() < EPS);
+        assert!((mid.alpha - 0.75).abs() < EPS);
+
+        let repeat = gradient(SpreadMethod::Repeat);
+        assert!((repeat.color_at(1.6).red - repeat.color_at(0.6).red).abs() < EPS);
+        assert!((repeat.color_at(-0.4).red - repeat.color_at(0.6).red).abs() < EPS);
+
+        let reflect = gradient(SpreadMethod::Reflect);
+        assert!((reflect.color_at(1.4).red - reflect.color_at(0.6).red).abs() < EPS);
+        assert!((reflect.color_at(-0.4).red - reflect.color_at(0.4).red).abs() < EPS);
+    }
+
+    #[test]
+    fn test_pieces() {
+        let pad = gradient(SpreadMethod::Pad);
+        let pieces = pad.pieces(0., 2., 8).unwrap();
+        let bounds = pieces.iter().map(|piece| (piece.0, piece.1)).collect::<Vec<_>>();
+        assert_eq!(bounds, vec![(0., 0.25), (0.25, 0.75), (0.75, 2.)]);
+        assert!((pieces[1].2.red - 1.).abs() < EPS && (pieces[1].3.blue - 0.5).abs() < EPS);
+        assert!((pieces[1].3.red).abs() < EPS && (pieces[2].2.blue - 0.5).abs() < EPS);
+
+        let repeat = gradient(SpreadMethod::Repeat);
+        let pieces = repeat.pieces(0.5, 1.5, 8).unwrap();
+        let bounds = pieces.iter().map(|piece| (piece.0, piece.1)).collect::<Vec<_>>();
+        assert_eq!(bounds, vec![(0.5, 0.75), (0.75, 1.), (1., 1.25), (1.25, 1.5)]);
+        // the color jumps at the end of the period
+        assert!((pieces[1].3.blue - 0.5).abs() < EPS && (pieces[2].2.red - 1.).abs() < EPS);
+
+        let reflect = gradient(SpreadMethod::Reflect);
+        let pieces = reflect.pieces(0.5, 1.5, 8).unwrap();
+        assert_eq!(pieces.len(), 4);
+        assert!((pieces[1].3.blue - 0.5).abs() < EPS && (pieces[2].2.blue - 0.5).abs() < EPS);
+        assert!(repeat.pieces(0., 100., 8).is_none());
+    }
+
+    #[test]
+    fn test_parameters() {
+        // a vertical gradient from y = 10 to y = 30, rotated by 90 degrees
+        let linear = LinearGradient {
+            gradient: gradient(SpreadMethod::Pad),
+            start: Point::new(0., 10.),
+            end: Point::new(0., 30.),
+            transform: Transform::rotate(::std::f32::consts::FRAC_PI_2),
+        };
+        let (direction, offset) = linear.parameter().unwrap();
+        let t = |x: f32, y: f32| direction.x * x + direction.y * y + offset;
+        assert!(t(-10., 5.).abs() < EPS);
+        assert!((t(-20., -5.) - 0.5).abs() < EPS);
+        assert!((t(-30., 0.) - 1.).abs() < EPS);
+
+        let radial = RadialGradient {
+            gradient: gradient(SpreadMethod::Pad),
+            center: Point::new(0., 0.),
+            radius: 2.,
+            focal: Point::new(1., 0.),
+            transform: Transform::identity(),
+        };
+        assert!(radial.parameter_at(Point::new(1., 0.)).abs() < EPS);
+        assert!((radial.parameter_at(Point::new(2., 0.)) - 1.).abs() < EPS);
+        assert!((radial.parameter_at(Point::new(-2., 0.)) - 1.).abs() < EPS);
+        assert!((radial.parameter_at(Point::new(-0.5, 0.)) - 0.5).abs() < EPS);
+        assert!((radial.parameter_at(Point::new(0., 2.)) - 1.).abs() < EPS);
+    }
+}
@@ -13,11 +13,15 @@ extern crate rayon;
 #[macro_use]
 extern crate serde_derive;
 
-use clap::{Arg, App, ArgGroup};
+use clap::{Arg, App, AppSettings, ArgGroup, ArgMatches, SubCommand};
 
 pub mod rasterizer;
 pub mod filter;
 pub mod geometry;
+pub mod blend;
+pub mod gradient;
+pub mod render;
+pub mod stroke;
 mod svg;
 
 use std::fs::File;
@@ -32,20 +36,21 @@ fn main() {
     let matches = App::new("svg-render")
         .version("0.1")
         .author("Manuel R.")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("named-filter")
                 .short("f")
                 .long("filter")
                 .value_name("name")
                 .help("Use one of the predefined filters")
-                .possible_values(&["box", "lanczos"]),
+                .possible_values(&["box", "gaussian", "jinc", "lanczos"]),
         )
         .arg(
             Arg::with_name("file-filter")
                 .short("c")
                 .long("custom-filter")
                 .value_name("path")
-                .help("Load und use a custom filter from path"),
+                .help("Load and use a custom filter in JSON or MessagePack format from path"),
         )
         .group(
             ArgGroup::with_name("filter")
@@ -74,70 +79,290 @@ fn main() {
                 .required(false)
                 .help("Dots per inch of rasterization"),
         )
+        .subcommand(
+            SubCommand::with_name("generate-filter")
+                .about("Computes the tiles of a piecewise polynomial filter")
+                .arg(
+                    Arg::with_name("definition")
+                        .required(true)
+                        .help("JSON file with the name, support and pieces of the filter")
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .required(true)
+                        .help(
+                            "Output filter file, written as MessagePack if it ends in \".filter\" \
+                             and as JSON otherwise",
+                        )
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("quadratic")
+                        .short("q")
+                        .long("quadratic")
+                        .help("Also compute tiles for quadratic curves"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fit-filter")
+                .about("Approximates a kernel with a polynomial on each tile")
+                .arg(
+                    Arg::with_name("kernel")
+                        .short("k")
+                        .long("kernel")
+                        .value_name("name")
+                        .help("Fit one of the predefined separable kernels")
+                        .possible_values(&["mitchell", "kaiser", "blackman-harris"]),
+                )
+                .arg(
+                    Arg::with_name("table")
+                        .short("t")
+                        .long("table")
+                        .value_name("path")
+                        .help("Fit the kernel sampled in a JSON file with its support and values"),
+                )
+                .group(
+                    ArgGroup::with_name("source")
+                        .arg("kernel")
+                        .arg("table")
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .required(true)
+                        .help(
+                            "Output filter file, written as MessagePack if it ends in \".filter\" \
+                             and as JSON otherwise",
+                        )
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("degree")
+                        .short("d")
+                        .long("degree")
+                        .value_name("num")
+                        .default_value("4")
+                        .validator(|value| match value.parse::<usize>() {
+                            Ok(degree) if degree <= filter::MAX_DEGREE => Ok(()),
+                            _ => Err(format!(
+                                "expected a whole number up to {}",
+                                filter::MAX_DEGREE
+                            )),
+                        })
+                        .help("Highest power of x and y in the polynomials"),
+                )
+                .arg(
+                    Arg::with_name("radius")
+                        .long("radius")
+                        .value_name("num")
+                        .default_value("2")
+                        .help("Radius of the Kaiser and Blackman-Harris windows"),
+                )
+                .arg(
+                    Arg::with_name("beta")
+                        .long("beta")
+                        .value_name("num")
+                        .default_value("4")
+                        .help("Shape parameter of the Kaiser window"),
+                )
+                .arg(
+                    Arg::with_name("quadratic")
+                        .short("q")
+                        .long("quadratic")
+                        .help("Also compute tiles for quadratic curves"),
+                ),
+        )
         .get_matches();
 
+    if let Some(matches) = matches.subcommand_matches("generate-filter") {
+        generate_filter(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("fit-filter") {
+        fit_filter(matches);
+        return;
+    }
+
 
     let default_size = (800., 600.);
 
-    let data = include_bytes!("../lanczos.json");
     let filter = match matches.value_of("named-filter") {
         Some("box") => FilterType::BoxFilter(filter::BoxFilter::new(1., 1.)),
-        Some("lanczos") => FilterType::Dynamic(serde_json::from_slice(data).unwrap()),
-        Some(_) => unreachable!(),
+        Some(name) => FilterType::Dynamic(bundled_filter(name)),
         // custom filter path provided
         None => {
             match matches.value_of("file-filter") {
-                Some(path) => {
-                    let file = File::open(path).unwrap();
-                    FilterType::Dynamic(serde_json::from_reader(file).unwrap())
-                }
+                Some(path) => FilterType::Dynamic(load_filter(path)),
                 None => FilterType::BoxFilter(filter::BoxFilter::new(1., 1.)),
             }
         }
     };
 
     let input_path = matches.value_of("input").expect("No input");
-    let mut input_file = File::open(input_path).unwrap();
     let mut svg = String::new();
-    input_file.read_to_string(&mut svg).unwrap();
+    if let Err(err) = File::open(input_path).and_then(|mut file| file.read_to_string(&mut svg)) {
+        eprintln!("error: could not read {}: {}", input_path, err);
+        std::process::exit(1);
+    }
 
-    let dpi = matches.value_of("dpi").expect("no dpi").parse().unwrap();
+    let dpi = matches.value_of("dpi").expect("no dpi");
+    let dpi = match dpi.parse() {
+        Ok(dpi) => dpi,
+        Err(err) => {
+            eprintln!("error: invalid resolution {}: {}", dpi, err);
+            std::process::exit(1);
+        }
+    };
 
-    let parsed_svg = svg::parse_str(&svg, dpi);
+    let parsed_svg = match svg::parse_str(&svg, dpi, default_size) {
+        Ok(parsed_svg) => parsed_svg,
+        Err(err) => {
+            eprintln!("error: could not read {}: {}", input_path, err);
+            std::process::exit(1);
+        }
+    };
     let size = parsed_svg.size.unwrap_or(default_size);
     let size = (size.0 as usize, size.1 as usize);
-    let curves = parsed_svg
-        .paths
-        .into_iter()
-        .flat_map(|path| path.lines.into_iter())
-        .collect::<Vec<_>>();
-
-    let mut buffer = Vec::new();
-    let viewport = geometry::Rect {
-        origin: geometry::Point::origin(),
-        size: geometry::Size {
-            width: size.0 as f32,
-            height: size.1 as f32,
-        },
-    };
 
-    println!("{:?}", curves);
-
-    match filter {
-        FilterType::BoxFilter(filter) => {
-            rasterizer::rasterize_parallel(viewport, &filter, &curves, &mut buffer)
-        }
-        FilterType::Dynamic(filter) => {
-            rasterizer::rasterize_parallel(viewport, &filter, &curves, &mut buffer)
-        }
-    }
+    let canvas = match filter {
+        FilterType::BoxFilter(ref filter) => render::render(filter, &parsed_svg, size),
+        FilterType::Dynamic(ref filter) => render::render(filter, &parsed_svg, size),
+    };
 
     let image_buffer = img::ImageBuffer::from_fn(size.0 as u32, size.1 as u32, |x, y| {
-        let v = buffer[y as usize * size.0 + x as usize];
-        let val = palette::Rgba::new(0.0, 0.0, 0.0, v);
+        let pixel = canvas.pixels()[y as usize * canvas.width() + x as usize];
+        let val = palette::Rgba::from(pixel);
         img::Rgba { data: palette::pixel::Srgb::linear_to_pixel(val) }
     });
 
     let output_path = matches.value_of("output").expect("No output");
-    image_buffer.save(output_path).unwrap();
+    if let Err(err) = image_buffer.save(output_path) {
+        eprintln!("error: could not write {}: {}", output_path, err);
+        std::process::exit(1);
+    }
+}
+
+/// Decodes one of the filters that are embedded into the binary.
+fn bundled_filter(name: &str) -> filter::DynamicFilter {
+    let data: &[u8] = match name {
+        "gaussian" => include_bytes!("../Gaussian.filter"),
+        "jinc" => include_bytes!("../Jinc.filter"),
+        "lanczos" => include_bytes!("../lanczos.filter"),
+        _ => unreachable!(),
+    };
+    filter::DynamicFilter::from_slice(data).expect("invalid bundled filter")
+}
+
+/// Reads the filter at `path`, exiting with an error message if that fails.
+fn load_filter(path: &str) -> filter::DynamicFilter {
+    let mut data = Vec::new();
+    if let Err(err) = File::open(path).and_then(|mut file| file.read_to_end(&mut data)) {
+        eprintln!("error: could not read {}: {}", path, err);
+        std::process::exit(1);
+    }
+    match filter::DynamicFilter::from_slice(&data) {
+        Ok(filter) => filter,
+        Err(err) => {
+            eprintln!("error: could not load filter {}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes the tiles of the filter defined by the `generate-filter` subcommand.
+fn generate_filter(matches: &ArgMatches) {
+    let definition_path = matches.value_of("definition").expect("No definition");
+    let definition = File::open(definition_path).map_err(|err| err.to_string()).and_then(
+        |file| serde_json::from_reader(file).map_err(|err| err.to_string()),
+    );
+    let definition: filter::PiecewisePolynomial = match definition {
+        Ok(definition) => definition,
+        Err(err) => {
+            eprintln!("error: could not read {}: {}", definition_path, err);
+            std::process::exit(1);
+        }
+    };
+    let filter = match definition.to_filter(matches.is_present("quadratic")) {
+        Ok(filter) => filter,
+        Err(err) => {
+            eprintln!("error: invalid filter {}: {}", definition_path, err);
+            std::process::exit(1);
+        }
+    };
+
+    write_filter(&filter, matches.value_of("output").expect("No output"));
+}
+
+/// Writes the tiles of a kernel approximated by the `fit-filter` subcommand.
+fn fit_filter(matches: &ArgMatches) {
+    let number = |name: &str| -> f64 {
+        let value = matches.value_of(name).expect("no default value");
+        value.parse().unwrap_or_else(|_| {
+            eprintln!("error: invalid {}: {}", name, value);
+            std::process::exit(1);
+        })
+    };
+    let degree = matches.value_of("degree").expect("no default value").parse().expect("validated");
+    let radius = number("radius");
+    let beta = number("beta");
+    let square = ((-radius as f32, radius as f32), (-radius as f32, radius as f32));
+
+    let fit = match matches.value_of("kernel") {
+        Some("mitchell") => {
+            let mitchell = |x| filter::mitchell_netravali(1. / 3., 1. / 3., x);
+            let support = ((-2., 2.), (-2., 2.));
+            filter::fit_kernel("Mitchell", support, degree, |x, y| mitchell(x) * mitchell(y))
+        }
+        Some("kaiser") => {
+            let kaiser = |x| filter::kaiser(radius, beta, x);
+            filter::fit_kernel("Kaiser", square, degree, |x, y| kaiser(x) * kaiser(y))
+        }
+        Some("blackman-harris") => {
+            let window = |x| filter::blackman_harris(radius, x);
+            filter::fit_kernel("Blackman-Harris", square, degree, |x, y| window(x) * window(y))
+        }
+        Some(_) => unreachable!(),
+        None => {
+            let table_path = matches.value_of("table").expect("No table");
+            let table: Result<filter::SampledKernel, _> = File::open(table_path)
+                .map_err(|err| err.to_string())
+                .and_then(|file| serde_json::from_reader(file).map_err(|err| err.to_string()));
+            let table = match table {
+                Ok(table) => table,
+                Err(err) => {
+                    eprintln!("error: could not read {}: {}", table_path, err);
+                    std::process::exit(1);
+                }
+            };
+            table.validate().and_then(|()| {
+                filter::fit_kernel("Sampled", table.support, degree, |x, y| table.value(x, y))
+            })
+        }
+    };
+    let quadratic = matches.is_present("quadratic");
+    let (filter, max_error) = match fit.and_then(|fit| {
+        fit.definition.to_filter(quadratic).map(|filter| (filter, fit.max_error))
+    }) {
+        Ok(result) => result,
+        Err(err) => {
+            eprintln!("error: invalid kernel: {}", err);
+            std::process::exit(1);
+        }
+    };
+    eprintln!("maximal error: {}", max_error);
+    write_filter(&filter, matches.value_of("output").expect("No output"));
+}
+
+/// Writes `filter` as MessagePack if `path` ends in ".filter" and as JSON otherwise.
+fn write_filter(filter: &filter::DynamicFilter, path: &str) {
+    let data = if path.ends_with(".filter") {
+        filter.to_message_pack()
+    } else {
+        filter.to_json()
+    };
+    if let Err(err) = File::create(path).and_then(|mut file| file.write_all(&data)) {
+        eprintln!("error: could not write {}: {}", path, err);
+        std::process::exit(1);
+    }
 }
@@ -1,9 +1,11 @@
-use arrayvec::ArrayVec;
+use arrayvec::{self, ArrayVec};
 use rayon::prelude::*;
 
-use geometry::{Rect, Line, ImageSize, Point, Size, Vec2d};
-use filter::{Filter, Evaluate};
+use geometry::{Rect, Line, QuadraticBezier, CubicBezier, Segment, ImageSize, Point, Size, Vec2d};
+use geometry::{solve_quadratic, cubic_roots_in_unit_interval};
+use filter::{BoxFilter, Filter, Evaluate};
 
+use std::iter;
 use std::sync::Mutex;
 
 pub trait Curve: Sized {
@@ -96,6 +98,206 @@ impl Curve for Line {
     }
 }
 
+impl Curve for QuadraticBezier {
+    type ClipIter = arrayvec::IntoIter<[Self; 5]>;
+
+    fn bounding_box(&self) -> Rect {
+        let endpoints = [self.start, self.end];
+        let extrema = self.extrema();
+        let points = endpoints.iter().cloned().chain(
+            extrema.iter().map(|&t| self.eval(t)),
+        );
+        Rect::enclosing(points)
+    }
+
+    fn clip_to_rect(&self, rect: Rect) -> Self::ClipIter {
+        let mut result = ArrayVec::new();
+        let rect = rect.normalize();
+        if !overlaps(self.bounding_box(), rect) {
+            return result.into_iter();
+        }
+
+        let poly = self.polynomial();
+        let xs = [poly[0].x, poly[1].x, poly[2].x];
+        let ys = [poly[0].y, poly[1].y, poly[2].y];
+        let mut splits: ArrayVec<[f32; 10]> = ArrayVec::new();
+        for &(c, value) in &edge_equations(rect, &xs, &ys) {
+            splits.extend(solve_quadratic(c[2], c[1], c[0] - value).into_iter());
+        }
+
+        for (t0, t1) in inside_intervals(rect, &mut splits, |t| self.eval(t)) {
+            let mut curve = self.subcurve(t0, t1);
+            curve.start = clamp_to_rect(curve.start, rect);
+            curve.end = clamp_to_rect(curve.end, rect);
+            if !on_far_edge(&[curve.start, curve.control, curve.end], rect) {
+                result.push(curve);
+            }
+        }
+
+        result.into_iter()
+    }
+
+    fn offset(&self, offset: Vec2d) -> QuadraticBezier {
+        QuadraticBezier {
+            start: self.start + offset,
+            control: self.control + offset,
+            end: self.end + offset,
+        }
+    }
+}
+
+impl Curve for CubicBezier {
+    type ClipIter = arrayvec::IntoIter<[Self; 8]>;
+
+    fn bounding_box(&self) -> Rect {
+        let endpoints = [self.start, self.end];
+        let extrema = self.extrema();
+        let points = endpoints.iter().cloned().chain(
+            extrema.iter().map(|&t| self.eval(t)),
+        );
+        Rect::enclosing(points)
+    }
+
+    fn clip_to_rect(&self, rect: Rect) -> Self::ClipIter {
+        let mut result = ArrayVec::new();
+        let rect = rect.normalize();
+        if !overlaps(self.bounding_box(), rect) {
+            return result.into_iter();
+        }
+
+        let poly = self.polynomial();
+        let xs = [poly[0].x, poly[1].x, poly[2].x, poly[3].x];
+        let ys = [poly[0].y, poly[1].y, poly[2].y, poly[3].y];
+        let mut splits: ArrayVec<[f32; 14]> = ArrayVec::new();
+        for &(c, value) in &edge_equations(rect, &xs, &ys) {
+            let roots = cubic_roots_in_unit_interval(c[3], c[2], c[1], c[0] - value);
+            splits.extend(roots.into_iter());
+        }
+
+        for (t0, t1) in inside_intervals(rect, &mut splits, |t| self.eval(t)) {
+            let mut curve = self.subcurve(t0, t1);
+            curve.start = clamp_to_rect(curve.start, rect);
+            curve.end = clamp_to_rect(curve.end, rect);
+            let points = [curve.start, curve.control1, curve.control2, curve.end];
+            if !on_far_edge(&points, rect) {
+                result.push(curve);
+            }
+        }
+
+        result.into_iter()
+    }
+
+    fn offset(&self, offset: Vec2d) -> CubicBezier {
+        CubicBezier {
+            start: self.start + offset,
+            control1: self.control1 + offset,
+            control2: self.control2 + offset,
+            end: self.end + offset,
+        }
+    }
+}
+
+impl Curve for Segment {
+    type ClipIter = arrayvec::IntoIter<[Self; 8]>;
+
+    fn bounding_box(&self) -> Rect {
+        match *self {
+            Segment::Line(ref line) => line.bounding_box(),
+            Segment::Quadratic(ref curve) => curve.bounding_box(),
+            Segment::Cubic(ref curve) => curve.bounding_box(),
+        }
+    }
+
+    fn clip_to_rect(&self, rect: Rect) -> Self::ClipIter {
+        let mut result = ArrayVec::new();
+        match *self {
+            Segment::Line(ref line) => result.extend(line.clip_to_rect(rect).map(Segment::Line)),
+            Segment::Quadratic(ref curve) => {
+                result.extend(curve.clip_to_rect(rect).map(Segment::Quadratic))
+            }
+            Segment::Cubic(ref curve) => {
+                result.extend(curve.clip_to_rect(rect).map(Segment::Cubic))
+            }
+        }
+        result.into_iter()
+    }
+
+    fn offset(&self, offset: Vec2d) -> Segment {
+        match *self {
+            Segment::Line(ref line) => Segment::Line(line.offset(offset)),
+            Segment::Quadratic(ref curve) => Segment::Quadratic(curve.offset(offset)),
+            Segment::Cubic(ref curve) => Segment::Cubic(curve.offset(offset)),
+        }
+    }
+}
+
+/// Pairs the coordinate polynomial of each edge's axis with the coordinate of that edge.
+fn edge_equations<'a, T>(rect: Rect, xs: &'a T, ys: &'a T) -> [(&'a T, f32); 4] {
+    [
+        (xs, rect.origin.x),
+        (xs, rect.origin.x + rect.size.width),
+        (ys, rect.origin.y),
+        (ys, rect.origin.y + rect.size.height),
+    ]
+}
+
+/// Sorts the parameters where a curve crosses the edges of `rect` and returns the parameter
+/// intervals in between in which the curve is inside of `rect`.
+///
+/// Adjacent intervals are merged into one.
+fn inside_intervals<F>(rect: Rect, splits: &mut [f32], eval: F) -> ArrayVec<[(f32, f32); 8]>
+where
+    F: Fn(f32) -> Point,
+{
+    let mut intervals = ArrayVec::new();
+    for t in splits.iter_mut() {
+        *t = t.max(0.).min(1.);
+    }
+    splits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+    let mut interval_start = None;
+    let mut t0 = 0.0;
+    for &t1 in splits.iter().chain(Some(1.0).iter()) {
+        if t1 <= t0 {
+            continue;
+        }
+        let inside = rect.is_inside(eval(0.5 * (t0 + t1)));
+        match (inside, interval_start) {
+            (true, None) => interval_start = Some(t0),
+            (false, Some(start)) => {
+                intervals.push((start, t0));
+                interval_start = None;
+            }
+            _ => {}
+        }
+        t0 = t1;
+    }
+    if let Some(start) = interval_start {
+        intervals.push((start, 1.0));
+    }
+    intervals
+}
+
+fn overlaps(a: Rect, b: Rect) -> bool {
+    a.origin.x <= b.origin.x + b.size.width && b.origin.x <= a.origin.x + a.size.width &&
+        a.origin.y <= b.origin.y + b.size.height && b.origin.y <= a.origin.y + a.size.height
+}
+
+fn clamp_to_rect(pt: Point, rect: Rect) -> Point {
+    Point::new(
+        pt.x.max(rect.origin.x).min(rect.origin.x + rect.size.width),
+        pt.y.max(rect.origin.y).min(rect.origin.y + rect.size.height),
+    )
+}
+
+/// Checks whether a curve with the given control points runs along the right or bottom edge of
+/// `rect`. Such curves belong to the neighbouring rect.
+fn on_far_edge(points: &[Point], rect: Rect) -> bool {
+    let xmax = rect.origin.x + rect.size.width;
+    let ymax = rect.origin.y + rect.size.height;
+    points.iter().all(|pt| pt.x == xmax) || points.iter().all(|pt| pt.y == ymax)
+}
+
 /// Stores the curves for each scanline
 struct ScanlineTable<C> {
     /// Stores indices to curves in `curves` that start at each scanline.
@@ -118,6 +320,30 @@ where
     };
 
     let size: ImageSize = viewport.size.into();
+    // only the curves whose bounding box comes close to a pixel are clipped to it, the margin
+    // keeps the curves that touch the pixel only after rounding in `offset`
+    let bounds = curves
+        .iter()
+        .map(|curve| {
+            let bbox = curve.bounding_box().normalize();
+            Rect::new(
+                bbox.origin.x - 0.5,
+                bbox.origin.y - 0.5,
+                bbox.size.width + 1.,
+                bbox.size.height + 1.,
+            )
+        })
+        .collect::<Vec<_>>();
+    let rows = (0..size.height)
+        .map(|row| {
+            let y = viewport.origin.y + row as f32;
+            (0..curves.len())
+                .filter(|&i| {
+                    bounds[i].origin.y <= y + 1. && y <= bounds[i].origin.y + bounds[i].size.height
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
     (0..size.width * size.height)
         .into_par_iter()
         .map(move |index| {
@@ -125,10 +351,17 @@ where
             let col = index % size.width;
 
             let pixel_origin = viewport.origin + Vec2d::new(col as f32, row as f32);
-            curves
+            rows[row]
                 .iter()
-                .flat_map(|curve| {
-                    curve.offset(-pixel_origin.vec_from_origin()).clip_to_rect(
+                .filter(|&&i| {
+                    let pixel = Rect {
+                        origin: pixel_origin,
+                        size: PIXEL_RECT.size,
+                    };
+                    overlaps(bounds[i], pixel)
+                })
+                .flat_map(|&i| {
+                    curves[i].offset(-pixel_origin.vec_from_origin()).clip_to_rect(
                         PIXEL_RECT,
                     )
                 })
@@ -137,6 +370,121 @@ where
         .collect()
 }
 
+/// Returns the smallest rect that contains all of the given curves.
+pub fn bounding_box<C: Curve>(curves: &[C]) -> Rect {
+    Rect::enclosing(curves.iter().flat_map(|curve| {
+        let bbox = curve.bounding_box().normalize();
+        iter::once(bbox.top_left()).chain(iter::once(bbox.bottom_right()))
+    }))
+}
+
+/// Cuts `curves` at the line of the points `p` with `normal · p = distance` and replaces the
+/// parts on the side that `normal` points to with their projection onto that line.
+///
+/// Together, a cut off part and its projection enclose nothing on the other side of the line, so
+/// the winding numbers stay the same there, while they become zero on the side of `normal`.
+pub fn clip_half_plane(curves: &[Segment], normal: Vec2d, distance: f32) -> Vec<Segment> {
+    let height = |point: Point| normal.dot(point.vec_from_origin()) - distance;
+    let project = |point: Point| point - normal * (height(point) / normal.dot(normal));
+
+    let mut result = Vec::with_capacity(curves.len());
+    for segment in curves {
+        // the curves lie in the convex hull of their control points
+        let points: ArrayVec<[Point; 4]> = match *segment {
+            Segment::Line(ref line) => [line.start, line.end].iter().cloned().collect(),
+            Segment::Quadratic(ref curve) => {
+                [curve.start, curve.control, curve.end].iter().cloned().collect()
+            }
+            Segment::Cubic(ref curve) => {
+                [curve.start, curve.control1, curve.control2, curve.end].iter().cloned().collect()
+            }
+        };
+        if points.iter().all(|&point| height(point) <= 0.) {
+            result.push(*segment);
+            continue;
+        }
+
+        let mut splits: ArrayVec<[f32; 3]> = ArrayVec::new();
+        match *segment {
+            Segment::Line(ref line) => {
+                let (start, end) = (height(line.start), height(line.end));
+                if start != end {
+                    splits.push(start / (start - end));
+                }
+            }
+            Segment::Quadratic(ref curve) => {
+                let poly = curve.polynomial();
+                let c = |i: usize| normal.dot(poly[i]);
+                splits.extend(solve_quadratic(c(2), c(1), c(0) - distance).into_iter());
+            }
+            Segment::Cubic(ref curve) => {
+                let poly = curve.polynomial();
+                let c = |i: usize| normal.dot(poly[i]);
+                let roots = cubic_roots_in_unit_interval(c(3), c(2), c(1), c(0) - distance);
+                splits.extend(roots.into_iter());
+            }
+        }
+        splits.retain(|&mut t| t.is_finite());
+        for t in splits.iter_mut() {
+            *t = t.max(0.).min(1.);
+        }
+        splits.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let mut t0 = 0.;
+        for &t1 in splits.iter().chain(Some(1.).iter()) {
+            if t1 <= t0 {
+                continue;
+            }
+            if height(segment.eval(0.5 * (t0 + t1))) <= 0. {
+                result.push(segment.subsegment(t0, t1));
+            } else {
+                let (start, end) = (project(segment.eval(t0)), project(segment.eval(t1)));
+                if start != end {
+                    result.push(Segment::Line(Line::new(start, end)));
+                }
+            }
+            t0 = t1;
+        }
+    }
+    result
+}
+
+/// Determines which regions enclosed by a path are considered inside.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FillRule {
+    /// Points with a non-zero winding number are inside.
+    NonZero,
+    /// Points with an odd winding number are inside.
+    EvenOdd,
+}
+
+impl Default for FillRule {
+    fn default() -> FillRule {
+        FillRule::NonZero
+    }
+}
+
+impl FillRule {
+    /// Maps the signed coverage produced by `rasterize_parallel` to a coverage in `[0, 1]`.
+    ///
+    /// The signed coverage is the filtered winding number, i.e. it takes integer values in the
+    /// interior of regions and interpolates in between at the edges. The winding number is mapped
+    /// to `0` or `1` according to the rule, the edges are interpolated linearly.
+    ///
+    /// `orientation` has the sign of the winding number around the pixel. Coverage of the
+    /// opposite sign stems from negative lobes of the filter and is clamped to `0`.
+    pub fn apply(self, coverage: f32, orientation: f32) -> f32 {
+        let coverage = if orientation < 0. { -coverage } else { coverage };
+        if coverage <= 0. {
+            return 0.;
+        }
+        match self {
+            FillRule::NonZero => coverage.min(1.),
+            FillRule::EvenOdd => 1. - (coverage % 2. - 1.).abs(),
+        }
+    }
+}
+
 /// Rasterize the given `curves` using the filter `filter` and put the pixel values into `buffer`.
 ///
 /// This uses rayon for parallelization where each filter piece and each scanline are evaluated in
@@ -148,15 +496,69 @@ pub fn rasterize_parallel<Flt, C>(viewport: Rect, filter: &Flt, curves: &[C], bu
 where
     Flt: Filter + Evaluate<C> + Sync,
     C: Curve + Clone + Send + Sync + ::std::fmt::Debug,
+{
+    rasterize_pieces(viewport, filter, curves, buffer, None, |_, _| (0., 0.));
+}
+
+/// Rasterizes `curves` like `rasterize_parallel` and maps the result to a coverage in `[0, 1]`
+/// using `fill_rule`.
+///
+/// The sign of the winding number of each pixel is taken from the unfiltered winding number
+/// integrated over the support of the filter around the pixel. It is accumulated in the same pass
+/// over the curves as the filtered coverage.
+pub fn rasterize_fill<Flt, C>(
+    viewport: Rect,
+    filter: &Flt,
+    curves: &[C],
+    fill_rule: FillRule,
+    buffer: &mut Vec<f32>,
+) where
+    Flt: Filter + Evaluate<C> + Sync,
+    BoxFilter: Evaluate<C>,
+    C: Curve + Clone + Send + Sync + ::std::fmt::Debug,
+{
+    let box_filter = BoxFilter::new(1., 1.);
+    let mut winding = Vec::new();
+    rasterize_pieces(
+        viewport,
+        filter,
+        curves,
+        buffer,
+        Some(&mut winding),
+        |curve, _| box_filter.eval(curve, (0, 0)),
+    );
+
+    for (value, &winding) in buffer.iter_mut().zip(&winding) {
+        *value = fill_rule.apply(*value, winding);
+    }
+}
+
+/// Rasterizes `curves` with `filter` into `buffer` and, if `winding` is given, accumulates
+/// `eval_winding` of the same curve pieces into it.
+///
+/// `eval_winding` is applied to the pieces of every filter piece, so each pixel of `winding`
+/// receives the sum over the whole support of the filter.
+fn rasterize_pieces<Flt, C, W>(
+    viewport: Rect,
+    filter: &Flt,
+    curves: &[C],
+    buffer: &mut Vec<f32>,
+    mut winding: Option<&mut Vec<f32>>,
+    eval_winding: W,
+) where
+    Flt: Filter + Evaluate<C> + Sync,
+    C: Curve + Clone + Send + Sync + ::std::fmt::Debug,
+    W: Fn(C, (u32, u32)) -> (f32, f32) + Sync,
 {
     let viewport = viewport.normalize();
     let size: ImageSize = viewport.size.into();
 
-    // prepare the image buffer
+    // prepare the image buffers
     buffer.clear();
-    buffer.reserve(size.width * size.height);
-    for _ in 0..(size.width * size.height) {
-        buffer.push(0.0);
+    buffer.resize(size.width * size.height, 0.0);
+    if let Some(ref mut winding) = winding {
+        winding.clear();
+        winding.resize(size.width * size.height, 0.0);
     }
 
     // Pixel Grid (pixels centers "+" are always in between whole coordinates)
@@ -172,7 +574,6 @@ where
     let x_filt_pieces = (support_x.1 - support_x.0) as usize;
     let y_filt_pieces = (support_y.1 - support_y.0) as usize;
 
-    println!("cutting curves");
     let curves_viewport = Rect {
         origin: viewport.origin +
             Vec2d {
@@ -185,13 +586,18 @@ where
         },
     };
     let curves = cut_curves(curves_viewport, curves);
-    println!("done");
 
     // Create a Mutex for each scanline, so they can be filled independently.
     let scanline_buffers = buffer
         .chunks_mut(size.width)
         .map(Mutex::new)
         .collect::<Vec<_>>();
+    let winding_buffers = winding.map(|winding| {
+        winding
+            .chunks_mut(size.width)
+            .map(Mutex::new)
+            .collect::<Vec<_>>()
+    });
 
     (0..x_filt_pieces * y_filt_pieces)
         .into_par_iter()
@@ -209,7 +615,11 @@ where
         })
         .for_each(|(filter_piece, scanline, chunk)| {
             let mut accumulator = 0.0;
+            let mut winding_accumulator = 0.0;
             let mut chunk = chunk.lock().unwrap();
+            let mut winding_chunk = winding_buffers
+                .as_ref()
+                .map(|buffers| buffers[scanline].lock().unwrap());
 
             // inner rendering loop
             for column in (0..size.width).rev() {
@@ -225,8 +635,223 @@ where
                     pixel_value += pv;
                     accumulator += acc;
                 }
-
                 chunk[column] += pixel_value;
+
+                if let Some(ref mut winding_chunk) = winding_chunk {
+                    let mut winding_value = winding_accumulator;
+                    for curve in curves[curve_index].iter().cloned() {
+                        let (wv, acc) = eval_winding(curve, filter_piece);
+                        winding_value += wv;
+                        winding_accumulator += acc;
+                    }
+                    winding_chunk[column] += winding_value;
+                }
             }
         });
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use filter::DynamicFilter;
+
+    const EPS: f32 = 1.0e-5;
+
+    fn square(x: f32, y: f32, size: f32) -> Vec<Line> {
+        let corners = [
+            Point::new(x, y),
+            Point::new(x + size, y),
+            Point::new(x + size, y + size),
+            Point::new(x, y + size),
+        ];
+        (0..4)
+            .map(|i| Line::new(corners[i], corners[(i + 1) % 4]))
+            .collect()
+    }
+
+    #[test]
+    fn test_clip_quadratic() {
+        let unit = Rect::new(0., 0., 1., 1.);
+
+        // touches the bottom edge at x = 0.5 and leaves through the left and right edges
+        let curve = QuadraticBezier::new(
+            Point::new(-0.5, 0.5),
+            Point::new(0.5, 1.5),
+            Point::new(1.5, 0.5),
+        );
+        let pieces = curve.clip_to_rect(unit).collect::<Vec<_>>();
+        assert_eq!(pieces.len(), 1);
+        assert!((pieces[0].start - Point::new(0., 0.875)).norm() < EPS);
+        assert!((pieces[0].end - Point::new(1., 0.875)).norm() < EPS);
+        assert!((pieces[0].eval(0.5) - Point::new(0.5, 1.)).norm() < EPS);
+
+        // leaves through the right edge and comes back
+        let curve = QuadraticBezier::new(
+            Point::new(0.5, 0.2),
+            Point::new(2.0, 0.5),
+            Point::new(0.5, 0.8),
+        );
+        let pieces = curve.clip_to_rect(unit).collect::<Vec<_>>();
+        assert_eq!(pieces.len(), 2);
+        assert_eq!(pieces[0].start, curve.start);
+        assert!((pieces[0].end.x - 1.).abs() < EPS);
+        assert!((pieces[1].start.x - 1.).abs() < EPS);
+        assert_eq!(pieces[1].end, curve.end);
+
+        let outside = curve.offset(Vec2d::new(3., 0.));
+        assert_eq!(outside.clip_to_rect(unit).count(), 0);
+    }
+
+    #[test]
+    fn test_clip_cubic() {
+        let unit = Rect::new(0., 0., 1., 1.);
+
+        // an s-shaped curve that leaves the rect through the top edge and comes back
+        let curve = CubicBezier::new(
+            Point::new(0.1, 0.5),
+            Point::new(0.5, -2.),
+            Point::new(0.5, 3.),
+            Point::new(0.9, 0.5),
+        );
+        let pieces = curve.clip_to_rect(unit).collect::<Vec<_>>();
+        assert_eq!(pieces.len(), 3);
+        assert_eq!(pieces[0].start, curve.start);
+        assert!(pieces[0].end.y.abs() < EPS && pieces[1].start.y.abs() < EPS);
+        assert!((pieces[1].end.y - 1.).abs() < EPS && (pieces[2].start.y - 1.).abs() < EPS);
+        assert_eq!(pieces[2].end, curve.end);
+    }
+
+    #[test]
+    fn test_rasterize_circle_area() {
+        // a circle with radius 3 made of four cubic curves
+        let k = 0.552_284_75 * 3.;
+        let center = Point::new(4.3, 3.8);
+        let pt = |x: f32, y: f32| center + Vec2d::new(x, y);
+        let curves = [
+            CubicBezier::new(pt(3., 0.), pt(3., k), pt(k, 3.), pt(0., 3.)),
+            CubicBezier::new(pt(0., 3.), pt(-k, 3.), pt(-3., k), pt(-3., 0.)),
+            CubicBezier::new(pt(-3., 0.), pt(-3., -k), pt(-k, -3.), pt(0., -3.)),
+            CubicBezier::new(pt(0., -3.), pt(k, -3.), pt(3., -k), pt(3., 0.)),
+        ];
+
+        let filter = BoxFilter::new(1., 1.);
+        let expected = curves.iter().map(|&c| filter.eval(c, (0, 0)).0).sum::<f32>();
+        assert!((expected.abs() - ::std::f32::consts::PI * 9.).abs() < 0.01);
+
+        let mut buffer = Vec::new();
+        rasterize_parallel(Rect::new(0., 0., 10., 10.), &filter, &curves, &mut buffer);
+        let area = buffer.iter().sum::<f32>();
+        assert!((area - expected).abs() < 1.0e-3);
+    }
+
+    #[test]
+    fn test_fill_rules() {
+        assert_eq!(FillRule::NonZero.apply(-2., -1.), 1.);
+        assert_eq!(FillRule::NonZero.apply(-0.25, -1.), 0.25);
+        assert_eq!(FillRule::NonZero.apply(-0.25, 1.), 0.);
+        assert_eq!(FillRule::EvenOdd.apply(2., 1.), 0.);
+        assert_eq!(FillRule::EvenOdd.apply(-1., -1.), 1.);
+        assert_eq!(FillRule::EvenOdd.apply(1.25, 1.), 0.75);
+
+        // two overlapping squares with the same orientation
+        let mut curves = square(1., 1., 4.);
+        curves.extend(square(3., 3., 4.));
+
+        let filter = BoxFilter::new(1., 1.);
+        let coverage = |rule: FillRule| {
+            let mut buffer = Vec::new();
+            rasterize_fill(Rect::new(0., 0., 8., 8.), &filter, &curves, rule, &mut buffer);
+            buffer.iter().sum::<f32>()
+        };
+        assert!((coverage(FillRule::NonZero) - 28.).abs() < EPS);
+        assert!((coverage(FillRule::EvenOdd) - 24.).abs() < EPS);
+    }
+
+    #[test]
+    fn test_fill_opposite_orientations() {
+        let filter = DynamicFilter::from_slice(include_bytes!("../Gaussian.filter")).unwrap();
+        let viewport = Rect::new(0., 0., 20., 10.);
+
+        // a small square alone and next to a larger square with the opposite orientation
+        let small = square(2., 4., 2.);
+        let mut curves = small.clone();
+        curves.extend(square(10., 2., 6.).into_iter().map(|line| Line::new(line.end, line.start)));
+
+        let (mut alone, mut together) = (Vec::new(), Vec::new());
+        rasterize_fill(viewport, &filter, &small, FillRule::NonZero, &mut alone);
+        rasterize_fill(viewport, &filter, &curves, FillRule::NonZero, &mut together);
+
+        // the blurred edges of the small square do not depend on the larger one
+        let halo = 3 * 20 + 2;
+        assert!(alone[halo] > 0.01);
+        for row in 0..10 {
+            for column in 0..8 {
+                let index = row * 20 + column;
+                assert!((alone[index] - together[index]).abs() < 1.0e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_clip_half_plane() {
+        let curves = [
+            Segment::Line(Line::new(Point::new(1., 1.), Point::new(5., 3.))),
+            Segment::Cubic(CubicBezier::new(
+                Point::new(5., 3.),
+                Point::new(5., 6.),
+                Point::new(0., 6.),
+                Point::new(1., 1.),
+            )),
+        ];
+        let clipped = clip_half_plane(&curves, Vec2d::new(1., 0.), 3.);
+        assert!(clipped.iter().all(|segment| {
+            let bbox = segment.bounding_box().normalize();
+            bbox.origin.x + bbox.size.width <= 3. + EPS
+        }));
+
+        // the pixels left of the cut are not affected
+        let filter = BoxFilter::new(1., 1.);
+        let (mut expected, mut buffer) = (Vec::new(), Vec::new());
+        rasterize_parallel(Rect::new(0., 0., 8., 8.), &filter, &curves, &mut expected);
+        rasterize_parallel(Rect::new(0., 0., 8., 8.), &filter, &clipped, &mut buffer);
+        for row in 0..8 {
+            for column in 0..3 {
+                let index = row * 8 + column;
+                assert!((buffer[index] - expected[index]).abs() < 1.0e-4);
+            }
+        }
+
+        // a diagonal cut through a square leaves half of its area
+        let square = square(0., 0., 2.).into_iter().map(Segment::Line).collect::<Vec<_>>();
+        let triangle = clip_half_plane(&square, Vec2d::new(1., 1.), 2.);
+        let area = triangle.iter().map(|&segment| filter.eval(segment, (0, 0)).0).sum::<f32>();
+        assert!((area.abs() - 2.).abs() < EPS);
+
+        // infinite control points make the roots NaN, which must not be sorted
+        let infinite = Segment::Cubic(CubicBezier::new(
+            Point::new(1., 1.),
+            Point::new(f32::INFINITY, 1.),
+            Point::new(f32::INFINITY, 3.),
+            Point::new(1., 1.),
+        ));
+        clip_half_plane(&[infinite], Vec2d::new(1., 0.), 3.);
+    }
+
+    #[test]
+    fn test_ringing() {
+        let filter = DynamicFilter::from_slice(include_bytes!("../lanczos.filter")).unwrap();
+        let curves = square(4., 4., 4.);
+        let viewport = Rect::new(0., 0., 12., 12.);
+        let mut signed = Vec::new();
+        rasterize_parallel(viewport, &filter, &curves, &mut signed);
+        let mut coverage = Vec::new();
+        rasterize_fill(viewport, &filter, &curves, FillRule::NonZero, &mut coverage);
+
+        // the negative lobes of the filter make the coverage outside of the edges change its sign
+        let inside = signed[5 * 12 + 5];
+        assert!((coverage[5 * 12 + 5] - 1.).abs() < 0.1);
+        let ringing = (0..signed.len()).filter(|&i| signed[i] * inside < -1.0e-3);
+        assert!(ringing.clone().count() > 0);
+        assert!(ringing.into_iter().all(|i| coverage[i] == 0.));
+    }
+}
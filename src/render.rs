@@ -0,0 +1,1041 @@
+use palette::{Rgb, Rgba, Blend};
+use palette::blend::PreAlpha;
+use palette::pixel::Srgb;
+
+use blend::BlendMode;
+use geometry::{Point, Vec2d, Rect, Segment, Transform};
+use filter::{DynamicFilter, Filter, Evaluate, Moments};
+use gradient::{Gradient, LinearGradient, RadialGradient};
+use rasterizer::{self, FillRule};
+use svg;
+
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+/// The largest number of intervals into which a gradient is split across a path, see
+/// `Gradient::pieces`. Beyond that the gradient is sampled.
+const MAX_GRADIENT_PIECES: usize = 64;
+
+/// The number of samples per pixel and direction for radial gradients that are not integrated
+/// against the filter.
+const RADIAL_SUBSAMPLES: usize = 4;
+
+/// The largest distance in pixels between the circles of a radial gradient and the polygons that
+/// replace them when the gradient is integrated against the filter.
+const RADIAL_TOLERANCE: f32 = 0.01;
+
+/// The largest number of edges of a polygon that replaces a circle of a radial gradient.
+const MAX_CIRCLE_EDGES: usize = 4096;
+
+/// A linear RGBA color with premultiplied alpha.
+pub type PreRgba = PreAlpha<Rgb<f32>, f32>;
+
+fn transparent() -> PreRgba {
+    PreRgba::from(Rgba::new(0., 0., 0., 0.))
+}
+
+/// Describes how the area covered by a path is colored.
+///
+/// The colors of gradient stops are in linear RGB as well and include all opacities.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Paint {
+    /// A uniform color in linear RGB, the alpha includes all opacities.
+    Color(Rgba),
+    LinearGradient(LinearGradient),
+    RadialGradient(RadialGradient),
+}
+
+impl Paint {
+    /// Returns the paint for the path after it has been transformed with `transform`.
+    pub fn transform(&self, transform: &Transform) -> Paint {
+        match *self {
+            Paint::Color(color) => Paint::Color(color),
+            Paint::LinearGradient(ref linear) => {
+                Paint::LinearGradient(LinearGradient {
+                    transform: *transform * linear.transform,
+                    ..linear.clone()
+                })
+            }
+            Paint::RadialGradient(ref radial) => {
+                Paint::RadialGradient(RadialGradient {
+                    transform: *transform * radial.transform,
+                    ..radial.clone()
+                })
+            }
+        }
+    }
+}
+
+/// The color of a paint at every pixel.
+enum Shader<'a> {
+    Solid(PreRgba),
+    Linear {
+        gradient: &'a Gradient,
+        direction: Vec2d,
+        offset: f32,
+    },
+    Radial {
+        radial: &'a RadialGradient,
+        inverse: Transform,
+    },
+}
+
+impl<'a> Shader<'a> {
+    fn new(paint: &'a Paint) -> Shader<'a> {
+        match *paint {
+            Paint::Color(color) => Shader::Solid(PreRgba::from(color)),
+            Paint::LinearGradient(ref linear) => {
+                match linear.parameter() {
+                    Some((direction, offset)) => {
+                        Shader::Linear {
+                            gradient: &linear.gradient,
+                            direction: direction,
+                            offset: offset,
+                        }
+                    }
+                    // a degenerate gradient is painted with the color of its last stop
+                    None => Shader::Solid(linear.gradient.color_at(1.)),
+                }
+            }
+            Paint::RadialGradient(ref radial) => {
+                match radial.transform.inverse() {
+                    Some(inverse) => {
+                        Shader::Radial {
+                            radial: radial,
+                            inverse: inverse,
+                        }
+                    }
+                    None => Shader::Solid(radial.gradient.color_at(1.)),
+                }
+            }
+        }
+    }
+
+    /// Returns the color of the pixel whose center lies at `center`.
+    ///
+    /// Linear gradients are sampled at the center and radial gradients are averaged over the
+    /// pixel, use `integrate` to filter them.
+    fn color_at(&self, center: Point) -> PreRgba {
+        match *self {
+            Shader::Solid(color) => color,
+            Shader::Linear {
+                gradient,
+                direction,
+                offset,
+            } => gradient.color_at(direction.dot(center.vec_from_origin()) + offset),
+            Shader::Radial { radial, inverse } => {
+                let n = RADIAL_SUBSAMPLES;
+                let step = 1. / n as f32;
+                let origin = center.offset(-0.5, -0.5);
+                (0..n * n).fold(transparent(), |sum, i| {
+                    let sample = origin.offset(
+                        ((i % n) as f32 + 0.5) * step,
+                        ((i / n) as f32 + 0.5) * step,
+                    );
+                    let t = radial.parameter_at(inverse.apply(sample));
+                    sum + radial.gradient.color_at(t) * (step * step)
+                })
+            }
+        }
+    }
+
+    /// Integrates the colors of a gradient times the filter over the area enclosed by `curves`
+    /// for each pixel of `viewport`, see `integrate_linear` and `integrate_radial`.
+    ///
+    /// Returns `None` for solid colors, without `moments` and for gradients that are split into
+    /// more than `MAX_GRADIENT_PIECES` bands across the path.
+    fn integrate<Flt>(
+        &self,
+        filter: &Flt,
+        moments: Option<&(DynamicFilter, DynamicFilter)>,
+        viewport: Rect,
+        curves: &[Segment],
+    ) -> Option<(Vec<PreRgba>, Vec<f32>)>
+    where
+        Flt: Filter + Evaluate<Segment> + Sync,
+    {
+        let moments = match moments {
+            Some(moments) => moments,
+            None => return None,
+        };
+        match *self {
+            Shader::Solid(_) => None,
+            Shader::Linear {
+                gradient,
+                direction,
+                offset,
+            } => integrate_linear(filter, moments, gradient, direction, offset, viewport, curves),
+            Shader::Radial { radial, inverse } => {
+                integrate_radial(filter, moments, radial, inverse, viewport, curves)
+            }
+        }
+    }
+}
+
+/// Integrates the colors of the linear gradient whose parameter is `direction · p + offset` times
+/// the filter over the area enclosed by `curves` for each pixel of `viewport`.
+///
+/// The gradient is split into bands on which its color is an affine function of the position.
+/// The filter is integrated over the part of the area in each band and, weighted with the
+/// position, gives the integral of the affine part of the color. Returns these integrals
+/// together with the signed coverage, i.e. the integrals of the filter alone. Their ratio is
+/// the filtered color wherever the area is enclosed once, overlapping parts count repeatedly.
+///
+/// Returns `None` if the gradient is split into more than `MAX_GRADIENT_PIECES` bands across the
+/// path.
+fn integrate_linear<Flt>(
+    filter: &Flt,
+    moments: &(DynamicFilter, DynamicFilter),
+    gradient: &Gradient,
+    direction: Vec2d,
+    offset: f32,
+    viewport: Rect,
+    curves: &[Segment],
+) -> Option<(Vec<PreRgba>, Vec<f32>)>
+where
+    Flt: Filter + Evaluate<Segment> + Sync,
+{
+    let (ref x_moment, ref y_moment) = *moments;
+    let parameter = |point: Point| direction.dot(point.vec_from_origin()) + offset;
+    let bbox = rasterizer::bounding_box(curves);
+    let corners = [
+        bbox.top_left(),
+        bbox.top_left().offset(bbox.size.width, 0.),
+        bbox.top_left().offset(0., bbox.size.height),
+        bbox.bottom_right(),
+    ];
+    let t0 = corners.iter().map(|&pt| parameter(pt)).fold(::std::f32::INFINITY, f32::min);
+    let t1 = corners.iter().map(|&pt| parameter(pt)).fold(::std::f32::NEG_INFINITY, f32::max);
+    let pieces = match gradient.pieces(t0, t1, MAX_GRADIENT_PIECES) {
+        Some(pieces) => pieces,
+        None => return None,
+    };
+
+    let width = viewport.size.width as usize;
+    let size = width * viewport.size.height as usize;
+    let mut colors = vec![transparent(); size];
+    let mut coverage = vec![0.; size];
+    let (mut values, mut x_values, mut y_values) = (Vec::new(), Vec::new(), Vec::new());
+    for (index, &(start, end, color0, color1)) in pieces.iter().enumerate() {
+        // the part of the area where the parameter lies between `start` and `end`
+        let mut band = curves.to_vec();
+        if index > 0 {
+            band = rasterizer::clip_half_plane(&band, -direction, offset - start);
+        }
+        if index + 1 < pieces.len() {
+            band = rasterizer::clip_half_plane(&band, direction, end - offset);
+        }
+        if band.is_empty() {
+            continue;
+        }
+
+        rasterizer::rasterize_parallel(viewport, filter, &band, &mut values);
+        for ((color, sum), &value) in colors.iter_mut().zip(&mut coverage).zip(&values) {
+            *color = *color + color0 * value;
+            *sum += value;
+        }
+        if color1 == color0 {
+            continue;
+        }
+
+        // the color is `color0 + s (color1 - color0)` with `s = (t - start) / (end - start)`,
+        // where `t` is `t(center) + direction · (p - center)` around the pixel center
+        rasterizer::rasterize_parallel(viewport, x_moment, &band, &mut x_values);
+        rasterizer::rasterize_parallel(viewport, y_moment, &band, &mut y_values);
+        let scale = 1. / (end - start);
+        for (i, color) in colors.iter_mut().enumerate() {
+            let column = (i % width) as f32 + 0.5;
+            let center = viewport.origin + Vec2d::new(column, (i / width) as f32 + 0.5);
+            let s = (parameter(center) - start) * values[i] + direction.x * x_values[i] +
+                direction.y * y_values[i];
+            *color = *color + (color1 - color0) * (s * scale);
+        }
+    }
+    Some((colors, coverage))
+}
+
+/// Integrates the colors of a radial gradient times the filter like `integrate_linear`.
+///
+/// Since the circles of the gradient are nested, the band of each piece is the part of the area
+/// inside of the circle at its end minus the part inside of the circle at its start. The circles
+/// are approximated by polygons, see `clip_to_circle`. Within the filter support around a pixel
+/// center, the parameter is approximated by its tangent plane at the center.
+fn integrate_radial<Flt>(
+    filter: &Flt,
+    moments: &(DynamicFilter, DynamicFilter),
+    radial: &RadialGradient,
+    inverse: Transform,
+    viewport: Rect,
+    curves: &[Segment],
+) -> Option<(Vec<PreRgba>, Vec<f32>)>
+where
+    Flt: Filter + Evaluate<Segment> + Sync,
+{
+    let (ref x_moment, ref y_moment) = *moments;
+    let parameter = |point: Point| radial.parameter_at(inverse.apply(point));
+
+    // only the part of the area within the filter support of the pixel centers affects the
+    // viewport
+    let ((support_x0, support_x1), (support_y0, support_y1)) = filter.support();
+    let bbox = rasterizer::bounding_box(curves);
+    let x0 = bbox.origin.x.max(viewport.origin.x + 0.5 + support_x0);
+    let y0 = bbox.origin.y.max(viewport.origin.y + 0.5 + support_y0);
+    let x1 = (bbox.origin.x + bbox.size.width)
+        .min(viewport.origin.x + viewport.size.width - 0.5 + support_x1);
+    let y1 = (bbox.origin.y + bbox.size.height)
+        .min(viewport.origin.y + viewport.size.height - 0.5 + support_y1);
+    if !(x0 <= x1 && y0 <= y1) {
+        return None;
+    }
+    let visible = [
+        Point::new(x0, y0),
+        Point::new(x1, y0),
+        Point::new(x0, y1),
+        Point::new(x1, y1),
+    ];
+    // the parameter is convex, since the circles are nested and grow linearly with it
+    let t1 = visible.iter().map(|&pt| parameter(pt)).fold(0., f32::max);
+    let pieces = match radial.gradient.pieces(0., t1, MAX_GRADIENT_PIECES) {
+        Some(pieces) => pieces,
+        None => return None,
+    };
+
+    let width = viewport.size.width as usize;
+    let size = width * viewport.size.height as usize;
+    let tangents = (0..size)
+        .map(|i| {
+            let column = (i % width) as f32 + 0.5;
+            let center = viewport.origin + Vec2d::new(column, (i / width) as f32 + 0.5);
+            let direction = Vec2d::new(
+                parameter(center.offset(0.5, 0.)) - parameter(center.offset(-0.5, 0.)),
+                parameter(center.offset(0., 0.5)) - parameter(center.offset(0., -0.5)),
+            );
+            (parameter(center), direction)
+        })
+        .collect::<Vec<_>>();
+    let mut colors = vec![transparent(); size];
+    let mut coverage = vec![0.; size];
+    // the integrals over the part of the area inside of the circle at the start of the piece
+    let (mut values, mut x_values, mut y_values) = (vec![0.; size], vec![0.; size], vec![0.; size]);
+    let (mut inner, mut x_inner, mut y_inner) = (Vec::new(), Vec::new(), Vec::new());
+    for (index, &(start, end, color0, color1)) in pieces.iter().enumerate() {
+        ::std::mem::swap(&mut values, &mut inner);
+        ::std::mem::swap(&mut x_values, &mut x_inner);
+        ::std::mem::swap(&mut y_values, &mut y_inner);
+        let disk = if index + 1 < pieces.len() {
+            clip_to_circle(curves, radial, end, &visible)
+        } else {
+            curves.to_vec()
+        };
+        rasterizer::rasterize_parallel(viewport, filter, &disk, &mut values);
+        rasterizer::rasterize_parallel(viewport, x_moment, &disk, &mut x_values);
+        rasterizer::rasterize_parallel(viewport, y_moment, &disk, &mut y_values);
+
+        // see `integrate_linear`
+        let scale = 1. / (end - start);
+        for (i, color) in colors.iter_mut().enumerate() {
+            let value = values[i] - inner[i];
+            coverage[i] += value;
+            *color = *color + color0 * value;
+            if color1 != color0 {
+                let (t, direction) = tangents[i];
+                let s = (t - start) * value + direction.x * (x_values[i] - x_inner[i]) +
+                    direction.y * (y_values[i] - y_inner[i]);
+                *color = *color + (color1 - color0) * (s * scale);
+            }
+        }
+    }
+    Some((colors, coverage))
+}
+
+/// Returns the part of the area enclosed by `curves` inside of the circle of `radial` at the
+/// parameter `t`, which is correct inside of the rectangle with the corners `visible`.
+///
+/// The circle is replaced by an inscribed polygon that deviates from it by at most
+/// `RADIAL_TOLERANCE` pixels. Only its edges that cross the rectangle are cut off.
+fn clip_to_circle(
+    curves: &[Segment],
+    radial: &RadialGradient,
+    t: f32,
+    visible: &[Point; 4],
+) -> Vec<Segment> {
+    let transform = radial.transform;
+    let center = radial.focal + (radial.center - radial.focal) * t;
+    let radius = radial.radius * t;
+    let scale = transform.apply_vec(Vec2d::new(1., 0.)).norm().max(
+        transform.apply_vec(Vec2d::new(0., 1.)).norm(),
+    );
+    // an edge of a regular polygon with `n` vertices on a circle with radius `r` is at most
+    // `r (1 - cos(π / n)) ≈ r π² / (2 n²)` away from the circle
+    let device_radius = radius * scale;
+    if !(device_radius > RADIAL_TOLERANCE) {
+        return Vec::new();
+    }
+    let edges = (PI * (device_radius / (2. * RADIAL_TOLERANCE)).sqrt()).ceil().max(8.);
+    let edges = edges.min(MAX_CIRCLE_EDGES as f32) as usize;
+
+    let vertex = |k: usize| {
+        let angle = 2. * PI * k as f32 / edges as f32;
+        transform.apply(center + Vec2d::new(angle.cos(), angle.sin()) * radius)
+    };
+    let device_center = transform.apply(center);
+    let mut result = curves.to_vec();
+    for k in 0..edges {
+        let (start, end) = (vertex(k), vertex(k + 1));
+        let mut normal = (end - start).orth();
+        if normal.dot(device_center - start) > 0. {
+            normal = -normal;
+        }
+        let distance = normal.dot(start.vec_from_origin());
+        let outside = visible
+            .iter()
+            .filter(|&&point| normal.dot(point.vec_from_origin()) > distance)
+            .count();
+        if outside == visible.len() {
+            return Vec::new();
+        }
+        if outside > 0 {
+            result = rasterizer::clip_half_plane(&result, normal, distance);
+        }
+    }
+    result
+}
+
+/// An image in linear RGB onto which paths are painted one after another.
+#[derive(Debug, Clone)]
+pub struct Canvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<PreRgba>,
+}
+
+/// A rectangular block of pixels `(x, y, width, height)`.
+type PixelRegion = (usize, usize, usize, usize);
+
+impl Canvas {
+    pub fn new(width: usize, height: usize) -> Canvas {
+        Canvas {
+            width: width,
+            height: height,
+            pixels: vec![transparent(); width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The pixel values in row major order.
+    pub fn pixels(&self) -> &[PreRgba] {
+        &self.pixels
+    }
+
+    /// Fills the area enclosed by `curves` with `paint` and composites it onto the canvas using
+    /// source-over.
+    ///
+    /// Only the pixels that the filter can reach from the bounding box of the curves are
+    /// rasterized. Gradients are integrated against the filter with its `moments`, see
+    /// `Moments`, and sampled without them.
+    pub fn fill<Flt>(
+        &mut self,
+        filter: &Flt,
+        moments: Option<&(DynamicFilter, DynamicFilter)>,
+        curves: &[Segment],
+        fill_rule: FillRule,
+        paint: &Paint,
+    ) where
+        Flt: Filter + Evaluate<Segment> + Sync,
+    {
+        self.fill_masked(filter, moments, curves, fill_rule, paint, None)
+    }
+
+    /// Like `fill`, but the coverage of every pixel is multiplied with the value of `mask`, which
+    /// has to be of the same size as the canvas.
+    pub fn fill_masked<Flt>(
+        &mut self,
+        filter: &Flt,
+        moments: Option<&(DynamicFilter, DynamicFilter)>,
+        curves: &[Segment],
+        fill_rule: FillRule,
+        paint: &Paint,
+        mask: Option<&Mask>,
+    ) where
+        Flt: Filter + Evaluate<Segment> + Sync,
+    {
+        let region = affected_region(self.width, self.height, filter, curves);
+        let (x, y, width, height) = match region {
+            Some(region) => region,
+            None => return,
+        };
+
+        let viewport = Rect::new(x as f32, y as f32, width as f32, height as f32);
+        let curves = clip_to_reach(self.width, filter, curves);
+        let mut coverage = Vec::new();
+        rasterizer::rasterize_fill(viewport, filter, &curves, fill_rule, &mut coverage);
+
+        let shader = Shader::new(paint);
+        let integrals = shader.integrate(filter, moments, viewport, &curves);
+        let visible_width = width.min(self.width - x);
+        for (row, values) in coverage.chunks(width).enumerate() {
+            let offset = (y + row) * self.width + x;
+            let pixels = &mut self.pixels[offset..offset + visible_width];
+            for (column, (pixel, &value)) in pixels.iter_mut().zip(values.iter()).enumerate() {
+                let alpha = match mask {
+                    Some(mask) => value * mask.values[offset + column],
+                    None => value,
+                };
+                if alpha == 0. {
+                    continue;
+                }
+                // the integrated color is divided by the coverage it was integrated over
+                let index = row * width + column;
+                let color = match integrals {
+                    Some((ref colors, ref coverage)) if coverage[index].abs() > 1.0e-6 => {
+                        colors[index] * (1. / coverage[index])
+                    }
+                    _ => {
+                        let center =
+                            Point::new((x + column) as f32 + 0.5, (y + row) as f32 + 0.5);
+                        shader.color_at(center)
+                    }
+                };
+                *pixel = (color * alpha).over(*pixel);
+            }
+        }
+    }
+
+    /// Composites `layer`, which has to be of the same size as the canvas, onto the canvas using
+    /// `blend_mode`, after multiplying each of its pixels with `opacity` and the value of `mask`.
+    ///
+    /// Since the layer is composited as a whole, overlapping paths on it are not faded twice.
+    pub fn composite(
+        &mut self,
+        layer: &Canvas,
+        blend_mode: BlendMode,
+        opacity: f32,
+        mask: Option<&Mask>,
+    ) {
+        for (index, (pixel, &source)) in self.pixels.iter_mut().zip(&layer.pixels).enumerate() {
+            let alpha = match mask {
+                Some(mask) => opacity * mask.values[index],
+                None => opacity,
+            };
+            if alpha == 0. {
+                continue;
+            }
+            *pixel = blend_mode.blend(source * alpha, *pixel);
+        }
+    }
+}
+
+/// Paints the paths of `graphic` in document order onto a canvas of the given size.
+pub fn render<Flt>(filter: &Flt, graphic: &svg::VectorGraphic, size: (usize, usize)) -> Canvas
+where
+    Flt: Filter + Evaluate<Segment> + Moments + Sync,
+{
+    let mut renderer = Renderer {
+        filter: filter,
+        moments: filter.moments(),
+        graphic: graphic,
+        size: size,
+        clip_masks: HashMap::new(),
+    };
+    let mut canvas = Canvas::new(size.0, size.1);
+    let (paths, groups, masks) = (graphic.paths.len(), graphic.groups.len(), graphic.masks.len());
+    renderer.paint(&mut canvas, 0, paths, groups, masks);
+    canvas
+}
+
+struct Renderer<'a, Flt: 'a> {
+    filter: &'a Flt,
+    /// The moments of the filter are computed only once for all gradients.
+    moments: Option<(DynamicFilter, DynamicFilter)>,
+    graphic: &'a svg::VectorGraphic,
+    size: (usize, usize),
+    /// Every combination of clip paths is rasterized only once.
+    clip_masks: HashMap<Vec<usize>, Mask>,
+}
+
+impl<'a, Flt> Renderer<'a, Flt>
+where
+    Flt: Filter + Evaluate<Segment> + Sync,
+{
+    /// Paints the paths `start..end` onto `canvas`.
+    ///
+    /// Only the groups and masks with an index below `group_limit` and `mask_limit` can lie
+    /// inside of the range, since the ones that contain the range are added later.
+    fn paint(
+        &mut self,
+        canvas: &mut Canvas,
+        start: usize,
+        end: usize,
+        group_limit: usize,
+        mask_limit: usize,
+    ) {
+        let graphic = self.graphic;
+        let mut index = start;
+        while index < end {
+            // the content of masks is only painted into the masks
+            let mask_end = graphic.masks[..mask_limit]
+                .iter()
+                .filter(|mask| mask.start == index && mask.end > index)
+                .map(|mask| mask.end)
+                .max();
+            if let Some(mask_end) = mask_end {
+                index = mask_end;
+                continue;
+            }
+            // the outermost of the groups that start here has been added last
+            let group = (0..group_limit).rev().find(|&group| graphic.groups[group].start == index);
+            if let Some(group_index) = group {
+                let group = &graphic.groups[group_index];
+                let mut layer = Canvas::new(self.size.0, self.size.1);
+                self.paint(&mut layer, group.start, group.end, group_index, mask_limit);
+                let mask = group.mask.map(|mask| self.mask(mask));
+                canvas.composite(&layer, group.blend_mode, group.opacity, mask.as_ref());
+                index = group.end;
+                continue;
+            }
+            self.fill(canvas, &graphic.paths[index]);
+            index += 1;
+        }
+    }
+
+    fn fill(&mut self, canvas: &mut Canvas, path: &svg::Path) {
+        let transform = self.graphic.transform;
+        let curves = transform_segments(&path.segments, &transform);
+        let paint = path.paint.transform(&transform);
+        if path.clip_paths.is_empty() {
+            canvas.fill(self.filter, self.moments.as_ref(), &curves, path.fill_rule, &paint);
+            return;
+        }
+
+        let (filter, graphic, size) = (self.filter, self.graphic, self.size);
+        let mask = self.clip_masks.entry(path.clip_paths.clone()).or_insert_with(|| {
+            let clip_mask = |index: usize| {
+                let mut mask = Mask::new(size.0, size.1);
+                for &(ref segments, fill_rule) in &graphic.clip_paths[index].shapes {
+                    mask.fill(filter, &transform_segments(segments, &transform), fill_rule);
+                }
+                mask
+            };
+            let mut mask = clip_mask(path.clip_paths[0]);
+            for &index in &path.clip_paths[1..] {
+                mask.intersect(&clip_mask(index));
+            }
+            mask
+        });
+        let moments = self.moments.as_ref();
+        canvas.fill_masked(filter, moments, &curves, path.fill_rule, &paint, Some(mask));
+    }
+
+    /// Paints the content of the mask with the given index and converts it into a mask.
+    fn mask(&mut self, index: usize) -> Mask {
+        let mask = &self.graphic.masks[index];
+        let mut content = Canvas::new(self.size.0, self.size.1);
+        let groups = self.graphic.groups.len();
+        self.paint(&mut content, mask.start, mask.end, groups, index);
+
+        let mut result = if mask.luminance {
+            Mask::from_luminance(&content)
+        } else {
+            Mask::from_alpha(&content)
+        };
+        let mut region = Mask::new(self.size.0, self.size.1);
+        let outline = transform_segments(&mask.region, &self.graphic.transform);
+        region.fill(self.filter, &outline, FillRule::NonZero);
+        result.intersect(&region);
+        result
+    }
+}
+
+fn transform_segments(segments: &[Segment], transform: &Transform) -> Vec<Segment> {
+    segments.iter().map(|segment| segment.transform(transform)).collect()
+}
+
+/// A value in `[0, 1]` for every pixel of a canvas that scales the coverage of painted paths.
+#[derive(Debug, Clone)]
+pub struct Mask {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+}
+
+impl Mask {
+    /// Creates a mask that hides everything.
+    pub fn new(width: usize, height: usize) -> Mask {
+        Mask {
+            width: width,
+            height: height,
+            values: vec![0.; width * height],
+        }
+    }
+
+    /// Creates a mask from the luminance of the colors of `canvas` in sRGB, weighted with their
+    /// alpha.
+    pub fn from_luminance(canvas: &Canvas) -> Mask {
+        Mask {
+            width: canvas.width,
+            height: canvas.height,
+            values: canvas
+                .pixels
+                .iter()
+                .map(|&pixel| {
+                    let color = Srgb::from_linear(Rgba::from(pixel));
+                    let luminance = 0.2125 * color.red + 0.7154 * color.green +
+                        0.0721 * color.blue;
+                    (luminance * color.alpha).max(0.).min(1.)
+                })
+                .collect(),
+        }
+    }
+
+    /// Creates a mask from the alpha of `canvas`.
+    pub fn from_alpha(canvas: &Canvas) -> Mask {
+        Mask {
+            width: canvas.width,
+            height: canvas.height,
+            values: canvas
+                .pixels
+                .iter()
+                .map(|pixel| pixel.alpha.max(0.).min(1.))
+                .collect(),
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The values in row major order.
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    /// Uncovers the area enclosed by `curves`, compositing its coverage like an opaque paint.
+    pub fn fill<Flt>(&mut self, filter: &Flt, curves: &[Segment], fill_rule: FillRule)
+    where
+        Flt: Filter + Evaluate<Segment> + Sync,
+    {
+        let region = affected_region(self.width, self.height, filter, curves);
+        let (x, y, width, height) = match region {
+            Some(region) => region,
+            None => return,
+        };
+
+        let viewport = Rect::new(x as f32, y as f32, width as f32, height as f32);
+        let curves = clip_to_reach(self.width, filter, curves);
+        let mut coverage = Vec::new();
+        rasterizer::rasterize_fill(viewport, filter, &curves, fill_rule, &mut coverage);
+
+        let visible_width = width.min(self.width - x);
+        for (row, values) in coverage.chunks(width).enumerate() {
+            let offset = (y + row) * self.width + x;
+            let pixels = &mut self.values[offset..offset + visible_width];
+            for (pixel, &alpha) in pixels.iter_mut().zip(values.iter()) {
+                *pixel = alpha + *pixel * (1. - alpha);
+            }
+        }
+    }
+
+    /// Restricts the mask to the area that is visible in `other` as well.
+    pub fn intersect(&mut self, other: &Mask) {
+        for (value, &other) in self.values.iter_mut().zip(other.values.iter()) {
+            *value *= other;
+        }
+    }
+}
+
+/// Returns the pixels whose filter support overlaps the bounding box of `curves`, clamped to
+/// an image of the given size.
+///
+/// On the right, the region extends beyond the image by the width of the filter support, since
+/// the rasterizer accumulates the coverage from right to left. The curves have to be cut with
+/// `clip_to_reach` so that nothing beyond the region is missed.
+fn affected_region<Flt>(
+    image_width: usize,
+    image_height: usize,
+    filter: &Flt,
+    curves: &[Segment],
+) -> Option<PixelRegion>
+where
+    Flt: Filter,
+{
+    if curves.is_empty() {
+        return None;
+    }
+    let bbox = rasterizer::bounding_box(curves);
+
+    // the pixel with index `i` sees the curves in `i + 0.5 + support`
+    let ((support_x0, support_x1), (support_y0, support_y1)) = filter.support();
+    let x0 = (bbox.origin.x - support_x1 - 0.5).floor().max(0.);
+    let y0 = (bbox.origin.y - support_y1 - 0.5).floor().max(0.);
+    let x1 = (bbox.origin.x + bbox.size.width - support_x0 - 0.5)
+        .ceil()
+        .min(image_width as f32 - 1. + (support_x1 - support_x0));
+    let y1 = (bbox.origin.y + bbox.size.height - support_y0 - 0.5)
+        .ceil()
+        .min(image_height as f32 - 1.);
+    if !(x0 <= x1 && x0 < image_width as f32 && y0 <= y1) {
+        return None;
+    }
+    Some((
+        x0 as usize,
+        y0 as usize,
+        (x1 - x0) as usize + 1,
+        (y1 - y0) as usize + 1,
+    ))
+}
+
+/// Replaces the parts of `curves` that no pixel of an image of the given width can see by their
+/// projection onto the right edge of what the filter of the last column reaches.
+fn clip_to_reach<Flt>(image_width: usize, filter: &Flt, curves: &[Segment]) -> Vec<Segment>
+where
+    Flt: Filter,
+{
+    let ((_, support_x1), _) = filter.support();
+    let reach = image_width as f32 - 0.5 + support_x1;
+    rasterizer::clip_half_plane(curves, Vec2d::new(1., 0.), reach)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use filter::BoxFilter;
+    use geometry::Line;
+    use gradient::SpreadMethod;
+
+    const EPS: f32 = 1.0e-5;
+
+    fn rectangle(x: f32, y: f32, width: f32, height: f32) -> Vec<Segment> {
+        let corners = [
+            Point::new(x, y),
+            Point::new(x + width, y),
+            Point::new(x + width, y + height),
+            Point::new(x, y + height),
+        ];
+        (0..4)
+            .map(|i| Segment::Line(Line::new(corners[i], corners[(i + 1) % 4])))
+            .collect()
+    }
+
+    #[test]
+    fn test_source_over() {
+        let filter = BoxFilter::new(1., 1.);
+        let red = Paint::Color(Rgba::new(1., 0., 0., 1.));
+        let blue = Paint::Color(Rgba::new(0., 0., 1., 0.5));
+        let mut canvas = Canvas::new(8, 8);
+        // the square covers half of each pixel in its first and last column
+        canvas.fill(&filter, None, &rectangle(1.5, 1., 3., 3.), FillRule::NonZero, &red);
+        canvas.fill(&filter, None, &rectangle(1.5, 1., 3., 3.), FillRule::NonZero, &blue);
+        canvas.fill(&filter, None, &rectangle(20., 1., 3., 3.), FillRule::NonZero, &red);
+        // partially outside of the canvas
+        canvas.fill(&filter, None, &rectangle(6., 5., 4., 4.), FillRule::NonZero, &red);
+
+        let pixel = |x: usize, y: usize| Rgba::from(canvas.pixels()[y * canvas.width() + x]);
+        let edge = pixel(1, 1);
+        assert!((edge.alpha - 0.625).abs() < EPS);
+        assert!((edge.color.red * edge.alpha - 0.375).abs() < EPS);
+        assert!((edge.color.blue * edge.alpha - 0.25).abs() < EPS);
+        let inside = pixel(2, 3);
+        assert!((inside.alpha - 1.).abs() < EPS);
+        assert!((inside.color.red - 0.5).abs() < EPS);
+        assert!((inside.color.blue - 0.5).abs() < EPS);
+        assert_eq!(pixel(0, 1).alpha, 0.);
+        assert_eq!(pixel(5, 2).alpha, 0.);
+        assert_eq!(pixel(2, 4).alpha, 0.);
+        assert_eq!(pixel(5, 6).alpha, 0.);
+        assert!((pixel(7, 6).alpha - 1.).abs() < EPS);
+    }
+
+    #[test]
+    fn test_wide_path() {
+        let filter = BoxFilter::new(1., 1.);
+        let red = Paint::Color(Rgba::new(1., 0., 0., 1.));
+        let mut canvas = Canvas::new(8, 4);
+        // the coverage of the interior is accumulated from the edge beyond the canvas
+        canvas.fill(&filter, None, &rectangle(2., 1., 20., 20.), FillRule::NonZero, &red);
+        let alpha = |x: usize, y: usize| canvas.pixels()[y * canvas.width() + x].alpha;
+        assert!((2..8).all(|x| (alpha(x, 2) - 1.).abs() < EPS));
+        assert_eq!(alpha(1, 2), 0.);
+        assert_eq!(alpha(5, 0), 0.);
+
+        // only the pixels that the filter can see beyond the edge are rasterized
+        let curves = rectangle(2., 1., 1.0e7, 1.0e7);
+        let region = affected_region(8, 4, &filter, &curves);
+        assert_eq!(region, Some((1, 0, 8, 4)));
+        let mut canvas = Canvas::new(8, 4);
+        canvas.fill(&filter, None, &curves, FillRule::NonZero, &red);
+        assert!((canvas.pixels()[3 * 8 + 7].alpha - 1.).abs() < EPS);
+    }
+
+    #[test]
+    fn test_mask() {
+        let filter = BoxFilter::new(1., 1.);
+        let red = Paint::Color(Rgba::new(1., 0., 0., 1.));
+        let mut mask = Mask::new(8, 8);
+        mask.fill(&filter, &rectangle(0., 0., 4., 4.), FillRule::NonZero);
+        // overlapping areas are not uncovered twice
+        mask.fill(&filter, &rectangle(2., 0., 2.5, 2.5), FillRule::NonZero);
+        let mut other = Mask::new(8, 8);
+        other.fill(&filter, &rectangle(0., 0., 8., 8.), FillRule::NonZero);
+        mask.intersect(&other);
+        assert!((mask.values()[4] - 0.5).abs() < EPS);
+
+        let mut canvas = Canvas::new(8, 8);
+        let curves = rectangle(2., 0., 4., 4.);
+        canvas.fill_masked(&filter, None, &curves, FillRule::NonZero, &red, Some(&mask));
+        let alpha = |x: usize, y: usize| canvas.pixels()[y * canvas.width() + x].alpha;
+        assert!((alpha(3, 1) - 1.).abs() < EPS);
+        assert!((alpha(4, 1) - 0.5).abs() < EPS);
+        assert_eq!(alpha(5, 1), 0.);
+        assert_eq!(alpha(1, 1), 0.);
+        assert_eq!(alpha(3, 5), 0.);
+    }
+
+    #[test]
+    fn test_composite() {
+        let filter = BoxFilter::new(1., 1.);
+        let white = Paint::Color(Rgba::new(1., 1., 1., 1.));
+        let mut content = Canvas::new(4, 1);
+        content.fill(&filter, None, &rectangle(0., 0., 2., 2.), FillRule::NonZero, &white);
+        let luminance = Mask::from_luminance(&content);
+        let alpha = Mask::from_alpha(&content);
+        for (&a, &b) in luminance.values().iter().zip(alpha.values()) {
+            assert!((a - b).abs() < 1.0e-3);
+        }
+
+        let mut layer = Canvas::new(4, 1);
+        let red = Paint::Color(Rgba::new(1., 0., 0., 1.));
+        layer.fill(&filter, None, &rectangle(1., 0., 3., 3.), FillRule::NonZero, &red);
+        let mut canvas = Canvas::new(4, 1);
+        canvas.composite(&layer, BlendMode::Normal, 1., Some(&luminance));
+        let alpha = canvas.pixels().iter().map(|pixel| pixel.alpha).collect::<Vec<_>>();
+        assert!(alpha[0] == 0. && (alpha[1] - 1.).abs() < 1.0e-3 && alpha[2] == 0.);
+
+        // the overlapping paths of a translucent layer do not darken each other
+        layer.fill(&filter, None, &rectangle(0., 0., 2., 2.), FillRule::NonZero, &red);
+        let mut canvas = Canvas::new(4, 1);
+        canvas.composite(&layer, BlendMode::Normal, 0.5, None);
+        let alpha = canvas.pixels().iter().map(|pixel| pixel.alpha).collect::<Vec<_>>();
+        assert!(alpha.iter().all(|&alpha| (alpha - 0.5).abs() < EPS));
+    }
+
+    #[test]
+    fn test_linear_gradient() {
+        let red = PreRgba::from(Rgba::new(1., 0., 0., 1.));
+        let blue = PreRgba::from(Rgba::new(0., 0., 1., 1.));
+        // a hard edge at x = 4.5 followed by a ramp from x = 6 to x = 8
+        let gradient = LinearGradient {
+            gradient: Gradient {
+                stops: vec![(0.45, red), (0.45, blue), (0.6, blue), (0.8, red)],
+                spread: SpreadMethod::Pad,
+            },
+            start: Point::new(0., 0.),
+            end: Point::new(1., 0.),
+            transform: Transform::scale(10., 1.),
+        };
+        let filter = BoxFilter::new(1., 1.);
+        let moments = filter.moments();
+        let mut canvas = Canvas::new(10, 2);
+        let paint = Paint::LinearGradient(gradient);
+        let curves = rectangle(0., 0., 10., 10.);
+        canvas.fill(&filter, moments.as_ref(), &curves, FillRule::NonZero, &paint);
+
+        let pixel = |x: usize| canvas.pixels()[x];
+        assert!((pixel(3).red - 1.).abs() < EPS && pixel(3).blue.abs() < EPS);
+        assert!((pixel(4).red - 0.5).abs() < EPS && (pixel(4).blue - 0.5).abs() < EPS);
+        assert!((pixel(5).blue - 1.).abs() < EPS);
+        assert!((pixel(6).red - 0.25).abs() < EPS);
+        assert!((pixel(7).red - 0.75).abs() < EPS);
+        assert!((pixel(9).red - 1.).abs() < EPS);
+        assert!(canvas.pixels().iter().all(|pixel| (pixel.alpha - 1.).abs() < EPS));
+
+        // the ramp is integrated only over the covered part of a pixel, between x = 6 and 6.5
+        let mut canvas = Canvas::new(10, 2);
+        let curves = rectangle(0., 0., 6.5, 2.);
+        canvas.fill(&filter, moments.as_ref(), &curves, FillRule::NonZero, &paint);
+        let pixel = canvas.pixels()[6];
+        assert!((pixel.alpha - 0.5).abs() < EPS);
+        assert!((pixel.red - 0.0625).abs() < EPS && (pixel.blue - 0.4375).abs() < EPS);
+
+        // filters with negative lobes reproduce a ramp away from the edges of the shape
+        let paint = Paint::LinearGradient(LinearGradient {
+            gradient: Gradient {
+                stops: vec![(0., red), (1., blue)],
+                spread: SpreadMethod::Pad,
+            },
+            start: Point::new(0., 0.),
+            end: Point::new(1., 0.),
+            transform: Transform::scale(10., 1.),
+        });
+        let filter = DynamicFilter::from_slice(include_bytes!("../lanczos.filter")).unwrap();
+        let moments = filter.moments();
+        let mut canvas = Canvas::new(10, 2);
+        let curves = rectangle(-10., -10., 30., 30.);
+        canvas.fill(&filter, moments.as_ref(), &curves, FillRule::NonZero, &paint);
+        let pixel = canvas.pixels()[4];
+        assert!((pixel.red - 0.55).abs() < 1.0e-4 && (pixel.blue - 0.45).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn test_radial_gradient() {
+        let red = PreRgba::from(Rgba::new(1., 0., 0., 1.));
+        let blue = PreRgba::from(Rgba::new(0., 0., 1., 1.));
+        let gradient = Gradient {
+            stops: vec![(0.5, red), (0.5, blue)],
+            spread: SpreadMethod::Pad,
+        };
+        // close to the hard edge at x = 4.5, the large circle looks like a straight line
+        let radial = Paint::RadialGradient(RadialGradient {
+            gradient: gradient.clone(),
+            center: Point::new(-1000., 1.),
+            radius: 2009.,
+            focal: Point::new(-1000., 1.),
+            transform: Transform::identity(),
+        });
+        let linear = Paint::LinearGradient(LinearGradient {
+            gradient: gradient,
+            start: Point::new(0., 0.),
+            end: Point::new(9., 0.),
+            transform: Transform::identity(),
+        });
+        let filter = DynamicFilter::from_slice(include_bytes!("../lanczos.filter")).unwrap();
+        let moments = filter.moments();
+        let curves = rectangle(-10., -10., 30., 30.);
+        let mut expected = Canvas::new(10, 2);
+        expected.fill(&filter, moments.as_ref(), &curves, FillRule::NonZero, &linear);
+        let mut canvas = Canvas::new(10, 2);
+        canvas.fill(&filter, moments.as_ref(), &curves, FillRule::NonZero, &radial);
+
+        // the negative lobes of the filter reach into the interior of the gradient
+        assert!(expected.pixels()[5].red < -1.0e-3);
+        for (pixel, other) in canvas.pixels().iter().zip(expected.pixels()) {
+            assert!((pixel.red - other.red).abs() < 1.0e-2);
+            assert!((pixel.blue - other.blue).abs() < 1.0e-2);
+        }
+    }
+
+    #[test]
+    fn test_unpaintable_groups() {
+        // the group and the masked rect lose all of their paths, which cannot be painted
+        let svg = r##"<svg width="10" height="10">
+            <mask id="mask"><rect width="10" height="10" fill="url(#missing)"/></mask>
+            <g opacity=".5"><rect width="5" height="5" fill="url(#missing)"/></g>
+            <rect width="5" height="5" fill="url(#missing)" mask="url(#mask)"/>
+            <rect width="10" height="10"/>
+        </svg>"##;
+        let graphic = svg::parse_str(svg, 96., (800., 600.)).unwrap();
+        assert!(graphic.groups.is_empty());
+        let canvas = render(&BoxFilter::new(1., 1.), &graphic, (10, 10));
+        assert!(canvas.pixels().iter().all(|pixel| (pixel.alpha - 1.).abs() < 1.0e-5));
+    }
+}
@@ -0,0 +1,677 @@
+use std::f32::consts::PI;
+
+use geometry::{Point, Vec2d, Segment, CubicBezier, EllipticalArc, PathBuilder, Subpath};
+
+/// Limits the subdivision of curves whose offset or length cannot be approximated well, e.g. at
+/// cusps.
+const MAX_SUBDIVISION_DEPTH: u32 = 8;
+
+/// The shape at the corners of a stroke.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineJoin {
+    /// Extends the outer edges until they meet, unless that exceeds the miter limit.
+    Miter,
+    Round,
+    /// Connects the outer edges with a straight line.
+    Bevel,
+}
+
+/// The shape at the ends of open subpaths.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineCap {
+    /// Ends the stroke exactly at the end point.
+    Butt,
+    Round,
+    /// Extends the stroke by half of its width.
+    Square,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct StrokeStyle {
+    pub width: f32,
+    pub join: LineJoin,
+    pub cap: LineCap,
+    /// The maximum ratio of the length of a miter to the stroke width, beyond which miter
+    /// joins are beveled.
+    pub miter_limit: f32,
+}
+
+impl Default for StrokeStyle {
+    fn default() -> StrokeStyle {
+        StrokeStyle {
+            width: 1.,
+            join: LineJoin::Miter,
+            cap: LineCap::Butt,
+            miter_limit: 4.,
+        }
+    }
+}
+
+/// Returns closed outlines that cover the stroke of `subpaths` when filled with the nonzero
+/// fill rule.
+///
+/// Curves are offset by cubic curves that deviate at most by about `tolerance` from the exact
+/// offset.
+pub fn stroke(subpaths: &[Subpath], style: &StrokeStyle, tolerance: f32) -> Vec<Segment> {
+    let stroker = Stroker {
+        style: style,
+        half_width: style.width / 2.,
+        tolerance: tolerance,
+    };
+    let mut builder = PathBuilder::new();
+    if stroker.half_width > 0. {
+        for subpath in subpaths {
+            stroker.stroke_subpath(&mut builder, subpath);
+        }
+    }
+    builder.into_segments()
+}
+
+/// Splits `subpaths` into the dashes of the pattern `dash_array`, which alternates between the
+/// lengths of dashes and gaps and is repeated along each subpath. The pattern is started at the
+/// distance `dash_offset` into it.
+///
+/// Dashes of zero length are returned as subpaths of zero length, which get caps when stroked.
+/// Lengths along curves are measured with an error of about `tolerance`. Returns the subpaths
+/// unchanged if the pattern is empty or invalid.
+pub fn dash(
+    subpaths: &[Subpath],
+    dash_array: &[f32],
+    dash_offset: f32,
+    tolerance: f32,
+) -> Vec<Subpath> {
+    let mut pattern = dash_array.to_vec();
+    // an odd number of lengths is repeated to give an even number
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_slice(dash_array);
+    }
+    let total = pattern.iter().sum::<f32>();
+    if pattern.iter().any(|&length| length < 0.) || !(total > 0.) {
+        return subpaths.to_vec();
+    }
+
+    // find the position in the pattern where each subpath starts
+    let mut start_index = 0;
+    let mut position = dash_offset % total;
+    if position < 0. {
+        position += total;
+    }
+    // a dash of zero length at the position itself is kept
+    while position > pattern[start_index] || position == pattern[start_index] &&
+        pattern[start_index] > 0.
+    {
+        position -= pattern[start_index];
+        start_index = (start_index + 1) % pattern.len();
+    }
+    let start_remaining = pattern[start_index] - position;
+
+    let mut dashes = Vec::new();
+    for subpath in subpaths {
+        let mut index = start_index;
+        let mut remaining = start_remaining;
+        let mut toggled = false;
+        let first_dash = dashes.len();
+        let mut current = Vec::new();
+        for segment in &subpath.segments {
+            let arc_length = ArcLength::new(segment, tolerance);
+            let length = arc_length.length();
+            let mut start = 0.;
+            while length - start > remaining {
+                let end = start + remaining;
+                if index % 2 == 0 {
+                    let (t0, t1) = (arc_length.parameter_at(start), arc_length.parameter_at(end));
+                    current.push(segment.subsegment(t0, t1));
+                    dashes.push(Subpath {
+                        segments: ::std::mem::replace(&mut current, Vec::new()),
+                        closed: false,
+                    });
+                }
+                start = end;
+                index = (index + 1) % pattern.len();
+                remaining = pattern[index];
+                toggled = true;
+            }
+            if index % 2 == 0 {
+                current.push(segment.subsegment(arc_length.parameter_at(start), 1.));
+            }
+            remaining -= length - start;
+        }
+
+        if !toggled {
+            // the subpath lies within a single dash or gap
+            if index % 2 == 0 {
+                dashes.push(subpath.clone());
+            }
+        } else if index % 2 == 0 {
+            // a dash across the start of a closed subpath is a single dash
+            if subpath.closed && start_index % 2 == 0 && first_dash < dashes.len() {
+                current.extend(dashes[first_dash].segments.drain(..));
+                dashes[first_dash].segments = current;
+            } else {
+                dashes.push(Subpath {
+                    segments: current,
+                    closed: false,
+                });
+            }
+        }
+
+        // a dash of zero length that starts right at the end of an open subpath
+        let next = (index + 1) % pattern.len();
+        if !subpath.closed && index % 2 == 1 && remaining == 0. && pattern[next] == 0. {
+            if let Some(last) = subpath.segments.last() {
+                dashes.push(Subpath {
+                    segments: vec![last.subsegment(1., 1.)],
+                    closed: false,
+                });
+            }
+        }
+    }
+    dashes
+}
+
+/// Maps arc lengths along a segment to parameters by means of a piecewise linear
+/// approximation.
+struct ArcLength {
+    /// Increasing parameters starting at 0 with the lengths of the segment up to them.
+    samples: Vec<(f32, f32)>,
+}
+
+impl ArcLength {
+    fn new(segment: &Segment, tolerance: f32) -> ArcLength {
+        let mut samples = vec![(0., 0.)];
+        match *segment {
+            Segment::Line(ref line) => samples.push((1., (line.end - line.start).norm())),
+            Segment::Quadratic(ref curve) => {
+                ArcLength::subdivide(&curve.to_cubic(), 0., 1., tolerance, 0, &mut samples)
+            }
+            Segment::Cubic(ref curve) => {
+                ArcLength::subdivide(curve, 0., 1., tolerance, 0, &mut samples)
+            }
+        }
+        ArcLength { samples: samples }
+    }
+
+    /// Adds samples for the part of a curve between `t0` and `t1`, which is given by `curve`.
+    ///
+    /// The length of a curve lies between the length of its chord and the length of its control
+    /// polygon, so it is approximated by their mean once they are close enough.
+    fn subdivide(
+        curve: &CubicBezier,
+        t0: f32,
+        t1: f32,
+        tolerance: f32,
+        depth: u32,
+        samples: &mut Vec<(f32, f32)>,
+    ) {
+        let chord = (curve.end - curve.start).norm();
+        let polygon = (curve.control1 - curve.start).norm() +
+            (curve.control2 - curve.control1).norm() + (curve.end - curve.control2).norm();
+        if polygon - chord > tolerance && depth < MAX_SUBDIVISION_DEPTH {
+            let t = 0.5 * (t0 + t1);
+            ArcLength::subdivide(&curve.subcurve(0., 0.5), t0, t, tolerance, depth + 1, samples);
+            ArcLength::subdivide(&curve.subcurve(0.5, 1.), t, t1, tolerance, depth + 1, samples);
+        } else {
+            let length = samples[samples.len() - 1].1 + 0.5 * (chord + polygon);
+            samples.push((t1, length));
+        }
+    }
+
+    fn length(&self) -> f32 {
+        self.samples[self.samples.len() - 1].1
+    }
+
+    fn parameter_at(&self, length: f32) -> f32 {
+        let index = match self.samples.iter().position(|&(_, l)| l > length) {
+            Some(index) => index,
+            None => return 1.,
+        };
+        if index == 0 {
+            return 0.;
+        }
+        let (t0, l0) = self.samples[index - 1];
+        let (t1, l1) = self.samples[index];
+        t0 + (t1 - t0) * (length - l0) / (l1 - l0)
+    }
+}
+
+struct Stroker<'a> {
+    style: &'a StrokeStyle,
+    half_width: f32,
+    tolerance: f32,
+}
+
+impl<'a> Stroker<'a> {
+    fn stroke_subpath(&self, builder: &mut PathBuilder, subpath: &Subpath) {
+        // segments without a direction contribute nothing but would break the joins
+        let forward = subpath
+            .segments
+            .iter()
+            .cloned()
+            .filter(|segment| segment.start_tangent().is_some())
+            .collect::<Vec<_>>();
+        if forward.is_empty() {
+            if let Some(segment) = subpath.segments.first() {
+                self.dot(builder, segment.start());
+            }
+            return;
+        }
+        let backward = forward.iter().rev().map(Segment::reverse).collect::<Vec<_>>();
+
+        let start = |segments: &[Segment]| {
+            self.offset_point(segments[0].start(), unit_start_tangent(&segments[0]))
+        };
+        if subpath.closed {
+            // the outlines on both sides are separate closed curves with opposite orientations
+            builder.move_to(start(&forward));
+            self.offset_side(builder, &forward, true);
+            builder.close();
+            builder.move_to(start(&backward));
+            self.offset_side(builder, &backward, true);
+            builder.close();
+        } else {
+            // a single outline that runs along one side and back along the other one
+            let last = forward.len() - 1;
+            builder.move_to(start(&forward));
+            self.offset_side(builder, &forward, false);
+            self.cap(builder, forward[last].end(), unit_end_tangent(&forward[last]));
+            self.offset_side(builder, &backward, false);
+            self.cap(builder, backward[last].end(), unit_end_tangent(&backward[last]));
+            builder.close();
+        }
+    }
+
+    /// Traces the side of `segments` that lies in the direction of `Vec2d::orth` of the
+    /// tangents, including the joins between the segments. The current point of `builder` has
+    /// to be the offset of the start point.
+    fn offset_side(&self, builder: &mut PathBuilder, segments: &[Segment], closed: bool) {
+        for (index, segment) in segments.iter().enumerate() {
+            self.offset_segment(builder, segment);
+            let next = match segments.get(index + 1) {
+                Some(next) => next,
+                None if closed => &segments[0],
+                None => break,
+            };
+            let incoming = unit_end_tangent(segment);
+            self.join(builder, segment.end(), incoming, unit_start_tangent(next));
+        }
+    }
+
+    fn offset_point(&self, point: Point, tangent: Vec2d) -> Point {
+        point + tangent.orth() * self.half_width
+    }
+
+    fn offset_segment(&self, builder: &mut PathBuilder, segment: &Segment) {
+        match *segment {
+            Segment::Line(ref line) => {
+                let normal = unit(line.end - line.start).orth() * self.half_width;
+                builder.line_to(line.end + normal);
+            }
+            Segment::Quadratic(ref curve) => self.offset_cubic(builder, &curve.to_cubic(), 0),
+            Segment::Cubic(ref curve) => self.offset_cubic(builder, curve, 0),
+        }
+    }
+
+    /// Approximates the offset of `curve` by moving the legs of its control polygon outwards
+    /// (Tiller and Hanson) and subdivides the curve until the approximation is good enough.
+    fn offset_cubic(&self, builder: &mut PathBuilder, curve: &CubicBezier, depth: u32) {
+        let segment = Segment::Cubic(*curve);
+        let (start_tangent, end_tangent) = match (segment.start_tangent(),
+                                                  segment.end_tangent()) {
+            (Some(start), Some(end)) => (unit(start), unit(end)),
+            _ => return,
+        };
+        let start = self.offset_point(curve.start, start_tangent);
+        let end = self.offset_point(curve.end, end_tangent);
+        let middle = curve.control2 - curve.control1;
+        let (control1, control2) = if middle == Vec2d::default() {
+            (self.offset_point(curve.control1, start_tangent),
+             self.offset_point(curve.control2, end_tangent))
+        } else {
+            let middle_offset = unit(middle).orth() * self.half_width;
+            let fallback1 = self.offset_point(curve.control1, start_tangent);
+            let fallback2 = self.offset_point(curve.control2, end_tangent);
+            (intersect(start, start_tangent, curve.control1 + middle_offset, middle)
+                 .unwrap_or(fallback1),
+             intersect(end, end_tangent, curve.control2 + middle_offset, middle)
+                 .unwrap_or(fallback2))
+        };
+        let offset = CubicBezier::new(start, control1, control2, end);
+
+        let error = [0.25, 0.5, 0.75]
+            .iter()
+            .map(|&t| {
+                let derivative = curve.derivative(t);
+                if derivative == Vec2d::default() {
+                    return 0.;
+                }
+                (offset.eval(t) - self.offset_point(curve.eval(t), unit(derivative))).norm()
+            })
+            .fold(0., f32::max);
+        if error > self.tolerance && depth < MAX_SUBDIVISION_DEPTH {
+            self.offset_cubic(builder, &curve.subcurve(0., 0.5), depth + 1);
+            self.offset_cubic(builder, &curve.subcurve(0.5, 1.), depth + 1);
+        } else {
+            builder.cubic_to(offset.control1, offset.control2, offset.end);
+        }
+    }
+
+    /// Connects the offset of the segment arriving at `vertex` in the direction `incoming` to
+    /// the offset of the segment leaving in the direction `outgoing`.
+    fn join(&self, builder: &mut PathBuilder, vertex: Point, incoming: Vec2d, outgoing: Vec2d) {
+        let normal_in = incoming.orth() * self.half_width;
+        let normal_out = outgoing.orth() * self.half_width;
+        let target = vertex + normal_out;
+        if outgoing.dot(normal_in) > 0. {
+            // On the inner side of the corner the offsets overlap. Passing through the vertex
+            // keeps the winding number of the overlap positive.
+            builder.line_to(vertex);
+            builder.line_to(target);
+            return;
+        }
+        let cos = incoming.dot(outgoing);
+        match self.style.join {
+            LineJoin::Miter if 1. + cos > 0. &&
+                               (2. / (1. + cos)).sqrt() <= self.style.miter_limit => {
+                builder.line_to(vertex + (normal_in + normal_out) / (1. + cos));
+            }
+            LineJoin::Round => {
+                let cross = normal_in.x * normal_out.y - normal_in.y * normal_out.x;
+                self.arc(builder, vertex, normal_in, cross.atan2(normal_in.dot(normal_out)));
+            }
+            _ => {}
+        }
+        builder.line_to(target);
+    }
+
+    /// Connects the offset at the end of a subpath that ends at `end` in the direction
+    /// `tangent` to the offset of the reversed subpath.
+    fn cap(&self, builder: &mut PathBuilder, end: Point, tangent: Vec2d) {
+        let normal = tangent.orth() * self.half_width;
+        match self.style.cap {
+            LineCap::Butt => {}
+            LineCap::Round => {
+                // the arc has to pass the point in front of the end
+                let counterclockwise = Vec2d::new(-normal.y, normal.x);
+                let sweep = if counterclockwise.dot(tangent) > 0. { PI } else { -PI };
+                self.arc(builder, end, normal, sweep);
+            }
+            LineCap::Square => {
+                let extension = tangent * self.half_width;
+                builder.line_to(end + normal + extension);
+                builder.line_to(end - normal + extension);
+            }
+        }
+        builder.line_to(end - normal);
+    }
+
+    /// Adds the caps of a subpath of zero length at `point`. Such a subpath has no direction, so
+    /// the caps are aligned with the x axis.
+    fn dot(&self, builder: &mut PathBuilder, point: Point) {
+        if self.style.cap == LineCap::Butt {
+            return;
+        }
+        let tangent = Vec2d::new(1., 0.);
+        builder.move_to(self.offset_point(point, tangent));
+        self.cap(builder, point, tangent);
+        self.cap(builder, point, -tangent);
+        builder.close();
+    }
+
+    /// Adds a circular arc around `center` that starts at `center + from`.
+    fn arc(&self, builder: &mut PathBuilder, center: Point, from: Vec2d, sweep: f32) {
+        builder.arc(&EllipticalArc {
+            center: center,
+            radii: Vec2d::new(self.half_width, self.half_width),
+            x_rotation: 0.,
+            start_angle: from.y.atan2(from.x),
+            sweep_angle: sweep,
+        });
+    }
+}
+
+fn unit(vec: Vec2d) -> Vec2d {
+    vec / vec.norm()
+}
+
+fn unit_start_tangent(segment: &Segment) -> Vec2d {
+    unit(segment.start_tangent().unwrap())
+}
+
+fn unit_end_tangent(segment: &Segment) -> Vec2d {
+    unit(segment.end_tangent().unwrap())
+}
+
+/// Intersects the line through `p` in the direction `u` with the line through `q` in the
+/// direction `v`. Returns `None` for (almost) parallel lines.
+fn intersect(p: Point, u: Vec2d, q: Point, v: Vec2d) -> Option<Point> {
+    let cross = u.x * v.y - u.y * v.x;
+    if cross.abs() <= 1.0e-3 * u.norm() * v.norm() {
+        return None;
+    }
+    let w = q - p;
+    let s = (w.x * v.y - w.y * v.x) / cross;
+    Some(p + u * s)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use geometry::{Line, Rect};
+    use rasterizer;
+
+    const EPS: f32 = 1.0e-4;
+
+    fn polyline(points: &[(f32, f32)], closed: bool) -> Subpath {
+        let mut builder = PathBuilder::new();
+        builder.move_to(Point::new(points[0].0, points[0].1));
+        for &(x, y) in &points[1..] {
+            builder.line_to(Point::new(x, y));
+        }
+        if closed {
+            builder.close();
+        }
+        builder.into_subpaths().remove(0)
+    }
+
+    fn style(join: LineJoin, cap: LineCap) -> StrokeStyle {
+        StrokeStyle {
+            width: 2.,
+            join: join,
+            cap: cap,
+            miter_limit: 4.,
+        }
+    }
+
+    fn contains_point(segments: &[Segment], point: Point) -> bool {
+        segments.iter().any(|segment| (segment.end() - point).norm() < EPS)
+    }
+
+    #[test]
+    fn test_caps() {
+        let line = [polyline(&[(0., 0.), (10., 0.)], false)];
+        let bbox = |cap| {
+            rasterizer::bounding_box(&stroke(&line, &style(LineJoin::Miter, cap), 0.01))
+        };
+
+        assert_eq!(bbox(LineCap::Butt), Rect::new(0., -1., 10., 2.));
+        assert_eq!(bbox(LineCap::Square), Rect::new(-1., -1., 12., 2.));
+        let round = bbox(LineCap::Round);
+        assert!((round.origin.x + 1.).abs() < EPS && (round.size.width - 12.).abs() < EPS);
+
+        // the outline of a butt cap is a rectangle
+        let outline = stroke(&line, &style(LineJoin::Miter, LineCap::Butt), 0.01);
+        assert_eq!(outline.len(), 4);
+        assert_eq!(
+            outline[1],
+            Segment::Line(Line::new(Point::new(10., -1.), Point::new(10., 1.)))
+        );
+    }
+
+    #[test]
+    fn test_joins() {
+        let corner = [polyline(&[(0., 0.), (10., 0.), (10., 10.)], false)];
+        let outline = |style| stroke(&corner, &style, 0.01);
+
+        let miter = outline(style(LineJoin::Miter, LineCap::Butt));
+        assert!(contains_point(&miter, Point::new(11., -1.)));
+        // the inner side passes through the corner itself
+        assert!(contains_point(&miter, Point::new(10., 0.)));
+
+        // a right angle has a miter ratio of sqrt(2)
+        let mut limited = style(LineJoin::Miter, LineCap::Butt);
+        limited.miter_limit = 1.4;
+        let bevel = outline(limited);
+        assert!(!contains_point(&bevel, Point::new(11., -1.)));
+        assert_eq!(bevel, outline(style(LineJoin::Bevel, LineCap::Butt)));
+
+        let round = outline(style(LineJoin::Round, LineCap::Butt));
+        let arcs = round
+            .iter()
+            .filter_map(|segment| match *segment {
+                Segment::Cubic(curve) => Some(curve),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert!(!arcs.is_empty());
+        for curve in arcs {
+            assert!(((curve.eval(0.5) - Point::new(10., 0.)).norm() - 1.).abs() < 1.0e-3);
+        }
+    }
+
+    #[test]
+    fn test_zero_length_subpaths() {
+        let mut builder = PathBuilder::new();
+        builder.move_to(Point::new(5., 5.));
+        builder.line_to(Point::new(5., 5.));
+        builder.move_to(Point::new(10., 5.));
+        builder.close();
+        // a move without a drawing command is no subpath
+        builder.move_to(Point::new(20., 5.));
+        let dots = builder.into_subpaths();
+        assert_eq!(dots.len(), 2);
+
+        let outline = |cap| stroke(&dots, &style(LineJoin::Miter, cap), 0.01);
+        assert!(outline(LineCap::Butt).is_empty());
+        assert_eq!(
+            rasterizer::bounding_box(&outline(LineCap::Square)),
+            Rect::new(4., 4., 7., 2.)
+        );
+        let round = outline(LineCap::Round);
+        assert!(round.iter().all(|segment| {
+            let center = if segment.start().x < 7.5 { 5. } else { 10. };
+            ((segment.eval(0.5) - Point::new(center, 5.)).norm() - 1.).abs() < 1.0e-3
+        }));
+        let bbox = rasterizer::bounding_box(&round);
+        assert!((bbox.origin.x - 4.).abs() < EPS && (bbox.size.width - 7.).abs() < EPS);
+    }
+
+    #[test]
+    fn test_dashes() {
+        let line = [polyline(&[(0., 0.), (10., 0.)], false)];
+        let dash_ends = |dash_array: &[f32], dash_offset| {
+            dash(&line, dash_array, dash_offset, 0.01)
+                .iter()
+                .map(|dash| {
+                    assert!(!dash.closed);
+                    (dash.segments[0].start().x, dash.segments[dash.segments.len() - 1].end().x)
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            dash_ends(&[2., 1.], 0.),
+            vec![(0., 2.), (3., 5.), (6., 8.), (9., 10.)]
+        );
+        assert_eq!(
+            dash_ends(&[2., 1.], 1.),
+            vec![(0., 1.), (2., 4.), (5., 7.), (8., 10.)]
+        );
+        // negative offsets shift the pattern forwards and odd patterns are repeated
+        assert_eq!(dash_ends(&[2., 1.], -1.), vec![(1., 3.), (4., 6.), (7., 9.)]);
+        assert_eq!(dash_ends(&[3.], 0.), vec![(0., 3.), (6., 9.)]);
+        // dashes of zero length
+        assert_eq!(dash_ends(&[0., 4.], 0.), vec![(0., 0.), (4., 4.), (8., 8.)]);
+        assert_eq!(dash_ends(&[0., 5.], 0.), vec![(0., 0.), (5., 5.), (10., 10.)]);
+        assert_eq!(dash_ends(&[2., 1.], 2.), vec![(1., 3.), (4., 6.), (7., 9.)]);
+        // invalid patterns give solid strokes
+        assert_eq!(dash(&line, &[], 0., 0.01), line.to_vec());
+        assert_eq!(dash(&line, &[0., 0.], 0., 0.01), line.to_vec());
+        assert_eq!(dash(&line, &[1., -1.], 0., 0.01), line.to_vec());
+
+        // the dash across the start of a closed subpath is not split
+        let square = [polyline(&[(0., 0.), (4., 0.), (4., 4.), (0., 4.)], true)];
+        let dashes = dash(&square, &[3., 2.], 0., 0.01);
+        assert_eq!(dashes.len(), 3);
+        assert_eq!(dashes[0].segments[0].start(), Point::new(0., 1.));
+        assert_eq!(dashes[0].segments[dashes[0].segments.len() - 1].end(), Point::new(3., 0.));
+
+        // lengths along curves
+        let mut builder = PathBuilder::new();
+        builder.move_to(Point::new(10., 0.));
+        builder.arc(&EllipticalArc {
+            center: Point::origin(),
+            radii: Vec2d::new(10., 10.),
+            x_rotation: 0.,
+            start_angle: 0.,
+            sweep_angle: 0.5 * ::std::f32::consts::PI,
+        });
+        let arc = builder.into_subpaths();
+        let dashes = dash(&arc, &[10. * ::std::f32::consts::PI / 4., 100.], 0., 0.001);
+        let end = dashes[0].segments[0].end();
+        assert!((end - Point::new(50f32.sqrt(), 50f32.sqrt())).norm() < 1.0e-2);
+    }
+
+    #[test]
+    fn test_dotted() {
+        let line = [polyline(&[(0., 0.), (10., 0.)], false)];
+        let dots = dash(&line, &[0., 4.], 0., 0.01);
+        assert!(stroke(&dots, &style(LineJoin::Miter, LineCap::Butt), 0.01).is_empty());
+
+        // each dash of zero length gives a circle around its position
+        let outline = stroke(&dots, &style(LineJoin::Miter, LineCap::Round), 0.01);
+        let mut centers = Vec::new();
+        for segment in &outline {
+            let center = Point::new(4. * (segment.start().x / 4.).round(), 0.);
+            assert!(((segment.eval(0.5) - center).norm() - 1.).abs() < 1.0e-3);
+            if !centers.contains(&center) {
+                centers.push(center);
+            }
+        }
+        assert_eq!(centers, vec![Point::new(0., 0.), Point::new(4., 0.), Point::new(8., 0.)]);
+    }
+
+    #[test]
+    fn test_closed_curves() {
+        use std::f32::consts::PI;
+
+        // a circle with radius 5 gives two circles with radius 4.5 and 5.5
+        let mut builder = PathBuilder::new();
+        builder.move_to(Point::new(5., 0.));
+        builder.arc(&EllipticalArc {
+            center: Point::origin(),
+            radii: Vec2d::new(5., 5.),
+            x_rotation: 0.,
+            start_angle: 0.,
+            sweep_angle: 2. * PI,
+        });
+        builder.close();
+        let outline = stroke(&builder.into_subpaths(), &StrokeStyle::default(), 0.001);
+        let mut radii = Vec::new();
+        for segment in &outline {
+            if let Segment::Cubic(curve) = *segment {
+                for &t in &[0., 0.3, 0.5, 0.8] {
+                    radii.push(curve.eval(t).vec_from_origin().norm());
+                }
+            }
+        }
+        assert!(radii.iter().all(|&r| (r - 4.5).abs() < 2.0e-3 || (r - 5.5).abs() < 2.0e-3));
+        assert!(radii.iter().any(|&r| r < 5.) && radii.iter().any(|&r| r > 5.));
+
+        // a closed polygon has joins at every corner but no caps
+        let square = [polyline(&[(0., 0.), (4., 0.), (4., 4.), (0., 4.)], true)];
+        let outline = stroke(&square, &style(LineJoin::Miter, LineCap::Square), 0.01);
+        assert_eq!(rasterizer::bounding_box(&outline), Rect::new(-1., -1., 6., 6.));
+        // the inner outline
+        assert!(contains_point(&outline, Point::new(1., 4.)));
+        assert!(contains_point(&outline, Point::new(3., 0.)));
+    }
+}
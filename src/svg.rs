@@ -1,54 +1,411 @@
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::mem;
+
 use svgparser;
-use svgparser::{AttributeId, ElementId, Length, LengthUnit, Tokenize, TextFrame};
+use svgparser::{AttributeId, ElementId, Length, LengthUnit, Tokenize, TextFrame, ValueId};
+use svgparser::PaintFallback;
 use svgparser::svg::{ElementEnd, Tokenizer, Token};
+use svgparser::path;
+use svgparser::style;
+use svgparser::transform;
+use palette::Rgba;
+use palette::pixel::Srgb;
+
+use blend::BlendMode;
+use geometry::{Point, Vec2d, Rect, Segment, Subpath, EllipticalArc, PathBuilder, Transform};
+use gradient::{Gradient, LinearGradient, RadialGradient, SpreadMethod};
+use rasterizer::{self, FillRule};
+use render::{Paint, PreRgba};
+use stroke::{self, LineCap, LineJoin, StrokeStyle};
+
+/// The maximum length of a chain of references, such that cycles are broken up.
+const MAX_REFERENCE_DEPTH: usize = 16;
+
+/// The maximum number of events that are processed again for references altogether, since
+/// nested references can instance elements exponentially often.
+const MAX_INSTANCED_EVENTS: usize = 1 << 20;
 
-use geometry::{Line, Point, Vec2d};
+/// The maximum deviation of stroke outlines from their exact shape in pixels.
+const STROKE_TOLERANCE: f32 = 0.02;
+
+/// The font size `medium` in user units, as used by most user agents.
+const MEDIUM_FONT_SIZE: f32 = 16.;
 
 #[derive(Debug, Default)]
 pub struct VectorGraphic {
     pub paths: Vec<Path>,
+    /// The clip paths that the paths refer to.
+    pub clip_paths: Vec<ClipPath>,
+    /// Ranges of paths that are composited as a whole. The range of a group ends before the
+    /// range of any group that contains it ends.
+    pub groups: Vec<Group>,
+    pub masks: Vec<Mask>,
     pub size: Option<(f32, f32)>,
+    /// Maps the user units of the paths to pixels of the output image.
+    pub transform: Transform,
 }
 
-#[derive(Debug, Default)]
+/// An area that is painted onto the image, either the interior of a shape or the outline of its
+/// stroke.
+#[derive(Debug)]
 pub struct Path {
-    pub lines: Vec<Line>,
+    pub segments: Vec<Segment>,
+    pub fill_rule: FillRule,
+    pub paint: Paint,
+    /// The indices of all clip paths in `VectorGraphic::clip_paths` that limit the path.
+    pub clip_paths: Vec<usize>,
 }
 
-#[derive(Debug, Default)]
-struct SvgRootMachine {
-    width: Option<f32>,
-    height: Option<f32>,
+/// Consecutive paths that are painted onto a layer of their own, which is then composited as a
+/// whole.
+#[derive(Debug)]
+pub struct Group {
+    /// The range `start..end` of the paths in `VectorGraphic::paths`. The ranges of two groups
+    /// are either disjoint or one contains the other.
+    pub start: usize,
+    pub end: usize,
+    /// Multiplies the alpha of the layer.
+    pub opacity: f32,
+    /// Mixes the layer with the paths below it.
+    pub blend_mode: BlendMode,
+    /// The index of the mask in `VectorGraphic::masks` that modulates the layer.
+    pub mask: Option<usize>,
+}
+
+/// Paths that are not painted onto the image, but whose luminance or alpha modulates a group.
+#[derive(Debug)]
+pub struct Mask {
+    /// The range `start..end` of the content in `VectorGraphic::paths`, which may contain the
+    /// content of other masks as well.
+    pub start: usize,
+    pub end: usize,
+    /// The outline of the mask region in the space of the paths, outside of which the mask hides
+    /// everything.
+    pub region: Vec<Segment>,
+    /// Whether the luminance of the content is used instead of its alpha.
+    pub luminance: bool,
+}
+
+/// Limits the painted area of paths to the union of its shapes.
+#[derive(Debug)]
+pub struct ClipPath {
+    /// The outlines of the shapes in the same space as the paths, together with their
+    /// `clip-rule`.
+    pub shapes: Vec<(Vec<Segment>, FillRule)>,
+}
+
+/// An error that prevents a document from being read.
+#[derive(Debug)]
+pub struct SvgError {
+    /// The line and column of the error, both counted from 1.
+    pub position: Option<(usize, usize)>,
+    /// The name of the innermost open element, if it is an SVG element.
+    pub element: Option<String>,
+    pub kind: SvgErrorKind,
+}
+
+#[derive(Debug)]
+pub enum SvgErrorKind {
+    /// The markup of the document is malformed.
+    Syntax(svgparser::Error),
+    /// The value of the named attribute cannot be parsed.
+    InvalidAttribute(String, svgparser::Error),
+    /// References instance more than `MAX_INSTANCED_EVENTS` events.
+    TooManyInstances,
+}
+
+impl SvgError {
+    fn new(kind: SvgErrorKind, element: Option<ElementId>) -> SvgError {
+        let position = match kind {
+            SvgErrorKind::Syntax(ref err) |
+            SvgErrorKind::InvalidAttribute(_, ref err) => error_position(err),
+            SvgErrorKind::TooManyInstances => None,
+        };
+        SvgError {
+            position: position,
+            element: element.map(|id| id.name().to_string()),
+            kind: kind,
+        }
+    }
+}
+
+impl fmt::Display for SvgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((line, column)) = self.position {
+            write!(f, "line {}, column {}: ", line, column)?;
+        }
+        match self.kind {
+            SvgErrorKind::Syntax(ref err) => write!(f, "{}", error_description(err))?,
+            SvgErrorKind::InvalidAttribute(ref name, ref err) => {
+                write!(f, "invalid value of attribute '{}' ({})", name, error_description(err))?
+            }
+            SvgErrorKind::TooManyInstances => write!(f, "references instance too many elements")?,
+        }
+        if let Some(ref element) = self.element {
+            write!(f, " in element '{}'", element)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for SvgError {
+    fn description(&self) -> &str {
+        match self.kind {
+            SvgErrorKind::Syntax(_) => "malformed SVG document",
+            SvgErrorKind::InvalidAttribute(..) => "invalid SVG attribute value",
+            SvgErrorKind::TooManyInstances => "too many instanced SVG elements",
+        }
+    }
+}
+
+/// Returns the line and column at which svgparser detected `err`.
+fn error_position(err: &svgparser::Error) -> Option<(usize, usize)> {
+    use svgparser::Error::*;
+    let pos = match *err {
+        UnexpectedEndOfStream(pos) |
+        InvalidChar { pos, .. } |
+        InvalidSvgToken(pos) |
+        UnexpectedClosingTag(pos) |
+        InvalidNumber(pos) |
+        InvalidLength(pos) |
+        InvalidColor(pos) |
+        InvalidTransform(pos) |
+        InvalidAttributeValue(pos) |
+        InvalidAdvance { pos, .. } => pos,
+        Utf8Error(_) => return None,
+    };
+    Some((pos.row, pos.col))
+}
+
+/// Describes `err` without its position, which svgparser includes in its own messages.
+fn error_description(err: &svgparser::Error) -> String {
+    use svgparser::Error::*;
+    match *err {
+        UnexpectedEndOfStream(_) => "unexpected end of document".to_string(),
+        InvalidChar { current, expected, .. } => {
+            format!("expected '{}', found '{}'", expected, current)
+        }
+        InvalidSvgToken(_) => "invalid token".to_string(),
+        UnexpectedClosingTag(_) => "closing tag without an opening tag".to_string(),
+        InvalidNumber(_) => "invalid number".to_string(),
+        InvalidLength(_) => "invalid length".to_string(),
+        InvalidColor(_) => "invalid color".to_string(),
+        InvalidTransform(_) => "invalid transform".to_string(),
+        InvalidAttributeValue(_) => "invalid value".to_string(),
+        InvalidAdvance { .. } => "unexpected end of document".to_string(),
+        Utf8Error(err) => err.to_string(),
+    }
+}
+
+/// The specified value of the `fill` and `stroke` properties.
+#[derive(Debug, Clone, PartialEq)]
+enum PaintValue {
+    None,
+    CurrentColor,
+    Color(svgparser::Color),
+    /// A reference to a paint server by its id and the paint used if the reference is invalid.
+    Server(String, Box<PaintValue>),
+}
+
+impl PaintValue {
+    /// Returns `None` for `inherit` and values that are not supported.
+    fn from_attribute(val: svgparser::AttributeValue) -> Option<PaintValue> {
+        match val {
+            svgparser::AttributeValue::PredefValue(ValueId::None) => Some(PaintValue::None),
+            svgparser::AttributeValue::PredefValue(ValueId::CurrentColor) => {
+                Some(PaintValue::CurrentColor)
+            }
+            svgparser::AttributeValue::Color(color) => Some(PaintValue::Color(color)),
+            svgparser::AttributeValue::FuncIRI(link) => {
+                Some(PaintValue::Server(link.to_string(), Box::new(PaintValue::None)))
+            }
+            svgparser::AttributeValue::FuncIRIWithFallback(link, fallback) => {
+                let fallback = match fallback {
+                    PaintFallback::PredefValue(ValueId::CurrentColor) => PaintValue::CurrentColor,
+                    PaintFallback::PredefValue(_) => PaintValue::None,
+                    PaintFallback::Color(color) => PaintValue::Color(color),
+                };
+                Some(PaintValue::Server(link.to_string(), Box::new(fallback)))
+            }
+            _ => None,
+        }
+    }
 }
 
 enum AttributeValue<'a> {
     Number(f32),
+    /// A percentage as a fraction, i.e. `50%` is passed as `0.5`.
+    Percentage(f32),
     NumberList(&'a mut Iterator<Item = f32>),
+    Transform(Transform),
     Other(svgparser::AttributeValue<'a>),
 }
 
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Align {
+    Min,
+    Mid,
+    Max,
+}
+
+impl Align {
+    fn from_str(name: &str) -> Option<Align> {
+        match name {
+            "Min" => Some(Align::Min),
+            "Mid" => Some(Align::Mid),
+            "Max" => Some(Align::Max),
+            _ => None,
+        }
+    }
+
+    /// The offset that aligns content of length `content` inside of `available`.
+    fn offset(self, available: f32, content: f32) -> f32 {
+        match self {
+            Align::Min => 0.,
+            Align::Mid => 0.5 * (available - content),
+            Align::Max => available - content,
+        }
+    }
+}
+
+/// The value of the `preserveAspectRatio` attribute.
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct PreserveAspectRatio {
+    /// The alignment along the x and y axes or `None` for non-uniform scaling.
+    align: Option<(Align, Align)>,
+    /// Whether the view box has to cover the whole viewport (`slice`) or only fit into it
+    /// (`meet`).
+    slice: bool,
+}
+
+impl Default for PreserveAspectRatio {
+    fn default() -> PreserveAspectRatio {
+        PreserveAspectRatio {
+            align: Some((Align::Mid, Align::Mid)),
+            slice: false,
+        }
+    }
+}
+
+impl PreserveAspectRatio {
+    /// Parses `[defer] <align> [meet | slice]`, falling back to the default for invalid values.
+    fn parse(text: &str) -> PreserveAspectRatio {
+        let mut words = text.split_whitespace().peekable();
+        if words.peek() == Some(&"defer") {
+            words.next();
+        }
+        let align = match words.next() {
+            Some("none") => None,
+            Some(align) if align.len() == 8 && align.is_ascii() && align.starts_with('x') &&
+                           &align[4..5] == "Y" => {
+                match (Align::from_str(&align[1..4]), Align::from_str(&align[5..8])) {
+                    (Some(x), Some(y)) => Some((x, y)),
+                    _ => return PreserveAspectRatio::default(),
+                }
+            }
+            _ => return PreserveAspectRatio::default(),
+        };
+        let slice = match words.next() {
+            None | Some("meet") => false,
+            Some("slice") => true,
+            Some(_) => return PreserveAspectRatio::default(),
+        };
+        PreserveAspectRatio {
+            align: align,
+            slice: slice,
+        }
+    }
+
+    /// Computes the transformation that maps `view_box` onto a viewport of the given size at the
+    /// origin.
+    fn view_box_transform(&self, view_box: Rect, viewport: (f32, f32)) -> Transform {
+        let mut scale_x = viewport.0 / view_box.size.width;
+        let mut scale_y = viewport.1 / view_box.size.height;
+        let mut translate_x = -view_box.origin.x * scale_x;
+        let mut translate_y = -view_box.origin.y * scale_y;
+        if let Some((align_x, align_y)) = self.align {
+            let scale = if self.slice {
+                scale_x.max(scale_y)
+            } else {
+                scale_x.min(scale_y)
+            };
+            scale_x = scale;
+            scale_y = scale;
+            translate_x = -view_box.origin.x * scale +
+                align_x.offset(viewport.0, view_box.size.width * scale);
+            translate_y = -view_box.origin.y * scale +
+                align_y.offset(viewport.1, view_box.size.height * scale);
+        }
+        Transform::new(scale_x, 0., 0., scale_y, translate_x, translate_y)
+    }
+}
+
+#[derive(Debug, Default)]
+struct SvgRootMachine {
+    width: Option<Coordinate>,
+    height: Option<Coordinate>,
+    view_box: Option<Rect>,
+    preserve_aspect_ratio: PreserveAspectRatio,
+}
+
 impl SvgRootMachine {
     fn new() -> Self {
         SvgRootMachine::default()
     }
 
     fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
-        let val = match val {
-            AttributeValue::Number(num) => num as f32,
-            _ => return,
-        };
-        match id {
-            AttributeId::Width => self.width = Some(val),
-            AttributeId::Height => self.height = Some(val),
+        match (id, val) {
+            (AttributeId::Width, val) => self.width = Coordinate::from_attribute(val),
+            (AttributeId::Height, val) => self.height = Coordinate::from_attribute(val),
+            (AttributeId::ViewBox, AttributeValue::NumberList(list)) => {
+                let numbers = list.take(4).collect::<Vec<_>>();
+                // a view box with a width or height of zero disables rendering, we simply ignore
+                // it instead
+                if numbers.len() == 4 && numbers[2] > 0. && numbers[3] > 0. {
+                    self.view_box = Some(Rect::new(numbers[0], numbers[1], numbers[2], numbers[3]));
+                }
+            }
+            (AttributeId::PreserveAspectRatio,
+             AttributeValue::Other(svgparser::AttributeValue::String(text))) => {
+                self.preserve_aspect_ratio = PreserveAspectRatio::parse(text);
+            }
             _ => {}
         }
     }
 
-    fn complete(self) -> Option<(f32, f32)> {
-        println!("svg machine {:?}", self);
-        match (self.width, self.height) {
-            (Some(width), Some(height)) => Some((width, height)),
+    /// Returns the size of the viewport together with the transformation from user units into
+    /// the viewport and the size of the viewport in user units.
+    ///
+    /// `default_size` is the size of the image that is used if the document does not give one.
+    /// Percentages in the size of the root refer to it as well.
+    fn complete(self, default_size: (f32, f32)) -> (Option<(f32, f32)>, Transform, (f32, f32)) {
+        let resolve = |length: Option<Coordinate>, reference: f32| match length {
+            Some(Coordinate::Number(num)) => Some(num),
+            Some(Coordinate::Fraction(fraction)) => Some(fraction * reference),
+            None => None,
+        };
+        // percentages refer to the embedding viewport, which has the default size
+        let width = resolve(self.width, default_size.0);
+        let height = resolve(self.height, default_size.1);
+        let size = match (width, height, self.view_box) {
+            (Some(width), Some(height), _) => Some((width, height)),
+            // without an explicit size the view box is rendered at one pixel per user unit
+            (width, height, Some(view_box)) => {
+                Some((
+                    width.unwrap_or(view_box.size.width),
+                    height.unwrap_or(view_box.size.height),
+                ))
+            }
             _ => None,
+        };
+        match (size, self.view_box) {
+            (Some(size), Some(view_box)) => {
+                let transform = self.preserve_aspect_ratio.view_box_transform(view_box, size);
+                (Some(size), transform, (view_box.size.width, view_box.size.height))
+            }
+            (size, _) => (size, Transform::identity(), size.unwrap_or(default_size)),
         }
     }
 }
@@ -59,7 +416,6 @@ struct LineMachine {
     y1: Option<f32>,
     x2: Option<f32>,
     y2: Option<f32>,
-    width: Option<f32>,
 }
 
 impl LineMachine {
@@ -77,12 +433,11 @@ impl LineMachine {
             AttributeId::X2 => self.x2 = Some(val),
             AttributeId::Y1 => self.y1 = Some(val),
             AttributeId::Y2 => self.y2 = Some(val),
-            AttributeId::StrokeWidth => self.width = Some(val),
             _ => {}
         };
     }
 
-    fn complete(self, lines: &mut Vec<Line>) {
+    fn complete(self, subpaths: &mut Vec<Subpath>) {
         let x1 = match self.x1 {
             Some(val) => val,
             None => return,
@@ -99,44 +454,38 @@ impl LineMachine {
             Some(val) => val,
             None => return,
         };
-        let width = self.width.unwrap_or(1.);
-
-        let v_orth = Vec2d::new(x2 - x1, y2 - y1).orth();
-        let v_orth_n = v_orth / v_orth.norm();
-
-        let start = Point::new(x1, y1);
-        let end = Point::new(x2, y2);
 
-        let p1 = start + v_orth_n * width / 2.;
-        let p2 = end + v_orth_n * width / 2.;
-        let p3 = end - v_orth_n * width / 2.;
-        let p4 = start - v_orth_n * width / 2.;
-
-        lines.push(Line::new(p1, p2));
-        lines.push(Line::new(p2, p3));
-        lines.push(Line::new(p3, p4));
-        lines.push(Line::new(p4, p1));
+        let mut builder = PathBuilder::new();
+        builder.move_to(Point::new(x1, y1));
+        builder.line_to(Point::new(x2, y2));
+        subpaths.extend(builder.into_subpaths());
     }
 }
 
+/// Machine for both `<polygon>` and `<polyline>` elements.
 #[derive(Debug)]
 struct PolygonMachine {
     pts: Vec<Point>,
+    /// Whether the outline is closed, which is only true for polygons.
+    closed: bool,
 }
 
 impl PolygonMachine {
-    fn new() -> PolygonMachine {
-        PolygonMachine { pts: Vec::new() }
+    fn new(closed: bool) -> PolygonMachine {
+        PolygonMachine {
+            pts: Vec::new(),
+            closed: closed,
+        }
     }
 
     fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
         match id {
             AttributeId::Points => {
                 self.pts.clear();
+                // svgparser reads `points` as a list of numbers or reports an error
                 if let AttributeValue::NumberList(list) = val {
                     let mut tmp = None;
                     for num in list.into_iter() {
-                        let num = num;
                         tmp = match tmp {
                             None => Some(num),
                             Some(x) => {
@@ -145,167 +494,2489 @@ impl PolygonMachine {
                             }
                         }
                     }
-                } else {
-                    panic!()
                 }
             }
             _ => {}
         }
     }
 
-    fn complete(self, lines: &mut Vec<Line>) {
+    /// Polygons and polylines are filled the same way, but only polygons have a stroke
+    /// between the last and the first point.
+    fn complete(self, subpaths: &mut Vec<Subpath>) {
         if self.pts.len() < 2 {
             return;
         }
 
-        let mut iter = self.pts.into_iter().peekable();
-        loop {
-            let pt = match iter.next() {
-                Some(el) => el,
-                None => break,
-            };
-            let next_pt = match iter.peek() {
-                Some(expr) => expr,
-                None => break,
-            };
-            lines.push(Line::new(pt, *next_pt));
+        let mut builder = PathBuilder::new();
+        builder.move_to(self.pts[0]);
+        for &pt in &self.pts[1..] {
+            builder.line_to(pt);
         }
+        if self.closed {
+            builder.close();
+        }
+        subpaths.extend(builder.into_subpaths());
     }
 }
 
 #[derive(Debug, Default)]
-struct Parser {
-    result: VectorGraphic,
-    stack: Vec<ParserState>,
-    dpi: f32,
+struct RectMachine {
+    x: Option<f32>,
+    y: Option<f32>,
+    width: Option<f32>,
+    height: Option<f32>,
+    rx: Option<f32>,
+    ry: Option<f32>,
 }
 
-impl Parser {
-    fn element_start(&mut self, id: ElementId) {
-        let elem = match id {
-            ElementId::Svg => Element::Svg(SvgRootMachine::new()),
-            ElementId::Line => Element::Line(LineMachine::new()),
-            ElementId::Polygon => Element::Polygon(PolygonMachine::new()),
+impl RectMachine {
+    fn new() -> Self {
+        RectMachine::default()
+    }
+
+    fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
+        let val = match val {
+            AttributeValue::Number(num) => num as f32,
             _ => return,
         };
-        self.state().elem = Some(elem);
+        match id {
+            AttributeId::X => self.x = Some(val),
+            AttributeId::Y => self.y = Some(val),
+            AttributeId::Width => self.width = Some(val),
+            AttributeId::Height => self.height = Some(val),
+            AttributeId::Rx => self.rx = Some(val),
+            AttributeId::Ry => self.ry = Some(val),
+            _ => {}
+        };
     }
 
-    fn attribute(&mut self, id: AttributeId, val: TextFrame) {
-        let dpi = self.dpi;
-        self.state().attribute(id, val, dpi)
-    }
+    fn complete(self, subpaths: &mut Vec<Subpath>) {
+        use std::f32::consts::{PI, FRAC_PI_2};
 
-    fn element_end(&mut self, end: ElementEnd) {
-        let current_state = self.stack.last_mut().unwrap();
-        match current_state.elem.take() {
-            None => {}
-            Some(Element::Svg(mach)) => {
-                self.result.size = mach.complete();
-            }
-            Some(Element::Line(mach)) => {
-                let mut new_path = Vec::with_capacity(4);
-                mach.complete(&mut new_path);
-                self.result.paths.push(Path { lines: new_path });
+        let x = self.x.unwrap_or(0.);
+        let y = self.y.unwrap_or(0.);
+        let (width, height) = match (self.width, self.height) {
+            (Some(width), Some(height)) if width > 0. && height > 0. => (width, height),
+            _ => return,
+        };
+
+        // a missing radius takes the value of the other one (SVG 1.1, section 9.2)
+        let (rx, ry) = match (self.rx, self.ry) {
+            (Some(rx), Some(ry)) => (rx, ry),
+            (Some(r), None) | (None, Some(r)) => (r, r),
+            (None, None) => (0., 0.),
+        };
+        let rx = rx.max(0.).min(width / 2.);
+        let ry = ry.max(0.).min(height / 2.);
+
+        let mut builder = PathBuilder::new();
+        if rx == 0. || ry == 0. {
+            builder.move_to(Point::new(x, y));
+            builder.line_to(Point::new(x + width, y));
+            builder.line_to(Point::new(x + width, y + height));
+            builder.line_to(Point::new(x, y + height));
+            builder.close();
+            subpaths.extend(builder.into_subpaths());
+            return;
+        }
+
+        let corner = |cx: f32, cy: f32, start_angle: f32| {
+            EllipticalArc {
+                center: Point::new(cx, cy),
+                radii: Vec2d::new(rx, ry),
+                x_rotation: 0.,
+                start_angle: start_angle,
+                sweep_angle: FRAC_PI_2,
             }
-            Some(Element::Polygon(mach)) => {
-                let mut new_path = Vec::new();
-                mach.complete(&mut new_path);
-                self.result.paths.push(Path { lines: new_path });
+        };
+        builder.move_to(Point::new(x + rx, y));
+        builder.line_to(Point::new(x + width - rx, y));
+        builder.arc(&corner(x + width - rx, y + ry, -FRAC_PI_2));
+        builder.line_to(Point::new(x + width, y + height - ry));
+        builder.arc(&corner(x + width - rx, y + height - ry, 0.));
+        builder.line_to(Point::new(x + rx, y + height));
+        builder.arc(&corner(x + rx, y + height - ry, FRAC_PI_2));
+        builder.line_to(Point::new(x, y + ry));
+        builder.arc(&corner(x + rx, y + ry, PI));
+        builder.close();
+        subpaths.extend(builder.into_subpaths());
+    }
+}
+
+/// Machine for both `<circle>` and `<ellipse>` elements.
+#[derive(Debug, Default)]
+struct EllipseMachine {
+    cx: Option<f32>,
+    cy: Option<f32>,
+    rx: Option<f32>,
+    ry: Option<f32>,
+}
+
+impl EllipseMachine {
+    fn new() -> Self {
+        EllipseMachine::default()
+    }
+
+    fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
+        let val = match val {
+            AttributeValue::Number(num) => num as f32,
+            _ => return,
+        };
+        match id {
+            AttributeId::Cx => self.cx = Some(val),
+            AttributeId::Cy => self.cy = Some(val),
+            AttributeId::R => {
+                self.rx = Some(val);
+                self.ry = Some(val);
             }
+            AttributeId::Rx => self.rx = Some(val),
+            AttributeId::Ry => self.ry = Some(val),
+            _ => {}
         };
     }
 
-    fn state(&mut self) -> &mut ParserState {
-        self.stack.last_mut().unwrap()
+    fn complete(self, subpaths: &mut Vec<Subpath>) {
+        use std::f32::consts::PI;
+
+        let (rx, ry) = match (self.rx, self.ry) {
+            (Some(rx), Some(ry)) if rx > 0. && ry > 0. => (rx, ry),
+            _ => return,
+        };
+        let center = Point::new(self.cx.unwrap_or(0.), self.cy.unwrap_or(0.));
+
+        let mut builder = PathBuilder::new();
+        builder.move_to(center.offset(rx, 0.));
+        builder.arc(&EllipticalArc {
+            center: center,
+            radii: Vec2d::new(rx, ry),
+            x_rotation: 0.,
+            start_angle: 0.,
+            sweep_angle: 2. * PI,
+        });
+        builder.close();
+        subpaths.extend(builder.into_subpaths());
     }
 }
 
-#[derive(Debug)]
-struct ParserState {
-    /// The element the parser is currently processing, if any.
-    elem: Option<Element>,
+#[derive(Debug, Default)]
+struct PathMachine {
+    subpaths: Vec<Subpath>,
 }
 
-impl ParserState {
-    fn attribute(&mut self, attr_id: AttributeId, val: TextFrame, dpi: f32) {
-        let elem_id = match self.elem {
-            Some(ref elem) => elem.element_id(),
-            None => return,
-        };
-        let val = svgparser::AttributeValue::from_frame(elem_id, attr_id, val).unwrap();
-        match val {
-            svgparser::AttributeValue::Number(num) => {
-                let val = AttributeValue::Number(num as f32);
-                self.elem.as_mut().unwrap().svg_attribute(attr_id, val)
-            }
-            svgparser::AttributeValue::NumberList(numbers) => {
-                let mut iter = numbers.map(|x| x.unwrap() as f32);
-                let val = AttributeValue::NumberList(&mut iter);
-                self.elem.as_mut().unwrap().svg_attribute(attr_id, val)
-            }
-            svgparser::AttributeValue::Length(Length { num, unit }) => {
-                let unit = match unit {
-                    LengthUnit::In => dpi,
-                    LengthUnit::Cm => dpi * 0.39,
-                    LengthUnit::Mm => dpi * 0.039,
-                    LengthUnit::Pt => dpi / 72.,
-                    LengthUnit::Pc => dpi / 6.,
-                    LengthUnit::None => 1.,
-                    _ => unimplemented!(),
-                };
-                let val = AttributeValue::Number(num as f32 * unit);
-                self.elem.as_mut().unwrap().svg_attribute(attr_id, val)
-            }
-            other => {
-                let val = AttributeValue::Other(other);
-                self.elem.as_mut().unwrap().svg_attribute(attr_id, val)
+impl PathMachine {
+    fn new() -> Self {
+        PathMachine::default()
+    }
+
+    fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
+        match (id, val) {
+            (AttributeId::D, AttributeValue::Other(svgparser::AttributeValue::String(data))) => {
+                self.subpaths = parse_path_data(data);
             }
-        };
+            _ => {}
+        }
+    }
+
+    fn complete(self, subpaths: &mut Vec<Subpath>) {
+        subpaths.extend(self.subpaths);
     }
 }
 
-#[derive(Debug)]
-enum Element {
-    Svg(SvgRootMachine),
-    Line(LineMachine),
-    Polygon(PolygonMachine),
+/// A coordinate of a gradient, which can be a fraction of the reference box.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Coordinate {
+    Number(f32),
+    Fraction(f32),
 }
 
-impl Element {
-    fn svg_attribute(&mut self, id: AttributeId, val: AttributeValue) {
-        match *self {
-            Element::Svg(ref mut svg_machine) => svg_machine.attribute(id, val),
-            Element::Line(ref mut line_machine) => line_machine.attribute(id, val),
-            Element::Polygon(ref mut polygon_machine) => polygon_machine.attribute(id, val),
+impl Coordinate {
+    fn from_attribute(val: AttributeValue) -> Option<Coordinate> {
+        match val {
+            AttributeValue::Number(num) => Some(Coordinate::Number(num)),
+            AttributeValue::Percentage(fraction) => Some(Coordinate::Fraction(fraction)),
+            _ => None,
         }
     }
 
-    fn element_id(&self) -> ElementId {
-        match *self {
-            Element::Svg(_) => ElementId::Svg,
-            Element::Line(_) => ElementId::Line,
-            Element::Polygon(_) => ElementId::Polygon,
+    fn resolve(self, reference: f32) -> f32 {
+        match self {
+            Coordinate::Number(num) => num,
+            Coordinate::Fraction(fraction) => fraction * reference,
         }
     }
 }
 
-pub fn parse_str(svg: &str, dpi: f32) -> VectorGraphic {
-    let mut tokenizer = Tokenizer::from_str(svg);
+/// Collects the attributes and stops of `linearGradient` and `radialGradient` elements.
+///
+/// Every attribute is optional, such that missing ones can be taken from the gradient that is
+/// referenced by `href`.
+#[derive(Debug, Clone, Default)]
+struct GradientMachine {
+    radial: bool,
+    id: Option<String>,
+    href: Option<String>,
+    user_space_units: Option<bool>,
+    transform: Option<Transform>,
+    spread: Option<SpreadMethod>,
+    x1: Option<Coordinate>,
+    y1: Option<Coordinate>,
+    x2: Option<Coordinate>,
+    y2: Option<Coordinate>,
+    cx: Option<Coordinate>,
+    cy: Option<Coordinate>,
+    r: Option<Coordinate>,
+    fx: Option<Coordinate>,
+    fy: Option<Coordinate>,
+    /// Offsets and colors, where the alpha is the `stop-opacity`.
+    stops: Vec<(f32, Rgba)>,
+}
 
-    let mut parser = Parser::default();
-    parser.dpi = dpi;
-    parser.stack.push(ParserState { elem: None });
+impl GradientMachine {
+    fn new(radial: bool) -> Self {
+        GradientMachine {
+            radial: radial,
+            ..GradientMachine::default()
+        }
+    }
 
-    loop {
-        match tokenizer.parse_next().unwrap() {
-            Token::SvgElementStart(id) => parser.element_start(id),
-            Token::ElementEnd(end) => parser.element_end(end),
-            Token::SvgAttribute(id, val) => parser.attribute(id, val),
-            Token::EndOfStream => break,
+    fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
+        use svgparser::AttributeValue::{String, IRI, PredefValue};
+        match (id, val) {
+            (AttributeId::Id, AttributeValue::Other(String(id))) => self.id = Some(id.to_string()),
+            (AttributeId::XlinkHref, AttributeValue::Other(IRI(link))) => {
+                self.href = Some(link.to_string())
+            }
+            (AttributeId::GradientUnits, AttributeValue::Other(PredefValue(units))) => {
+                self.user_space_units = Some(units == ValueId::UserSpaceOnUse)
+            }
+            (AttributeId::GradientTransform, AttributeValue::Transform(transform)) => {
+                self.transform = Some(transform)
+            }
+            (AttributeId::SpreadMethod, AttributeValue::Other(PredefValue(spread))) => {
+                self.spread = match spread {
+                    ValueId::Reflect => Some(SpreadMethod::Reflect),
+                    ValueId::Repeat => Some(SpreadMethod::Repeat),
+                    _ => Some(SpreadMethod::Pad),
+                }
+            }
+            (AttributeId::X1, val) => self.x1 = Coordinate::from_attribute(val),
+            (AttributeId::Y1, val) => self.y1 = Coordinate::from_attribute(val),
+            (AttributeId::X2, val) => self.x2 = Coordinate::from_attribute(val),
+            (AttributeId::Y2, val) => self.y2 = Coordinate::from_attribute(val),
+            (AttributeId::Cx, val) => self.cx = Coordinate::from_attribute(val),
+            (AttributeId::Cy, val) => self.cy = Coordinate::from_attribute(val),
+            (AttributeId::R, val) => self.r = Coordinate::from_attribute(val),
+            (AttributeId::Fx, val) => self.fx = Coordinate::from_attribute(val),
+            (AttributeId::Fy, val) => self.fy = Coordinate::from_attribute(val),
             _ => {}
+        }
+    }
+
+    /// Takes all attributes that are not specified from the referenced gradient `template`.
+    fn inherit(&mut self, template: &GradientMachine) {
+        if self.radial == template.radial {
+            self.x1 = self.x1.or(template.x1);
+            self.y1 = self.y1.or(template.y1);
+            self.x2 = self.x2.or(template.x2);
+            self.y2 = self.y2.or(template.y2);
+            self.cx = self.cx.or(template.cx);
+            self.cy = self.cy.or(template.cy);
+            self.r = self.r.or(template.r);
+            self.fx = self.fx.or(template.fx);
+            self.fy = self.fy.or(template.fy);
+        }
+        self.user_space_units = self.user_space_units.or(template.user_space_units);
+        self.transform = self.transform.or(template.transform);
+        self.spread = self.spread.or(template.spread);
+        if self.stops.is_empty() {
+            self.stops = template.stops.clone();
+        }
+    }
+
+    /// Creates the paint for a path with the bounding box `bbox` in user space.
+    ///
+    /// `transform` maps the user space to the space of the path and `viewport` is the size of
+    /// the viewport in user units. Returns `None` if nothing should be painted.
+    fn to_paint(
+        &self,
+        bbox: Rect,
+        transform: Transform,
+        opacity: f32,
+        viewport: (f32, f32),
+    ) -> Option<Paint> {
+        // offsets have to be increasing
+        let mut stops = Vec::with_capacity(self.stops.len());
+        let mut last_offset = 0.;
+        for &(offset, color) in &self.stops {
+            last_offset = offset.max(last_offset).min(1.);
+            let color = Rgba::new(color.red, color.green, color.blue, color.alpha * opacity);
+            stops.push((last_offset, PreRgba::from(color)));
+        }
+        let last_color = match stops.last() {
+            Some(&(_, color)) => Rgba::from(color),
+            None => return None,
+        };
+        if stops.len() == 1 {
+            return Some(Paint::Color(last_color));
+        }
+        let gradient = Gradient {
+            stops: stops,
+            spread: self.spread.unwrap_or_default(),
+        };
+
+        let (units, reference) = if self.user_space_units.unwrap_or(false) {
+            (Transform::identity(), viewport)
+        } else {
+            if bbox.size.width == 0. || bbox.size.height == 0. {
+                return None;
+            }
+            let units = Transform::new(
+                bbox.size.width,
+                0.,
+                0.,
+                bbox.size.height,
+                bbox.origin.x,
+                bbox.origin.y,
+            );
+            (units, (1., 1.))
+        };
+        let transform = transform * units * self.transform.unwrap_or_default();
+        let x = |coordinate: Option<Coordinate>, default| {
+            coordinate.unwrap_or(Coordinate::Fraction(default)).resolve(reference.0)
         };
+        let y = |coordinate: Option<Coordinate>, default| {
+            coordinate.unwrap_or(Coordinate::Fraction(default)).resolve(reference.1)
+        };
+
+        if !self.radial {
+            return Some(Paint::LinearGradient(LinearGradient {
+                gradient: gradient,
+                start: Point::new(x(self.x1, 0.), y(self.y1, 0.)),
+                end: Point::new(x(self.x2, 1.), y(self.y2, 0.)),
+                transform: transform,
+            }));
+        }
+
+        let center = Point::new(x(self.cx, 0.5), y(self.cy, 0.5));
+        let diagonal = (reference.0 * reference.0 + reference.1 * reference.1).sqrt();
+        let radius = self.r
+            .unwrap_or(Coordinate::Fraction(0.5))
+            .resolve(diagonal / 2f32.sqrt());
+        if radius < 0. {
+            return None;
+        } else if radius == 0. {
+            return Some(Paint::Color(last_color));
+        }
+        // a focal point outside of the circle is moved onto the circle, and slightly inside of it
+        // to avoid the degenerate case
+        let mut focal = Point::new(
+            self.fx.map_or(center.x, |fx| fx.resolve(reference.0)),
+            self.fy.map_or(center.y, |fy| fy.resolve(reference.1)),
+        );
+        let max_distance = 0.999 * radius;
+        if (focal - center).norm() > max_distance {
+            focal = center + (focal - center) * (max_distance / (focal - center).norm());
+        }
+        Some(Paint::RadialGradient(RadialGradient {
+            gradient: gradient,
+            center: center,
+            radius: radius,
+            focal: focal,
+            transform: transform,
+        }))
+    }
+}
+
+#[derive(Debug, Default)]
+struct StopMachine {
+    offset: Option<f32>,
+    /// `None` stands for `currentColor`.
+    color: Option<Option<svgparser::Color>>,
+    opacity: Option<f32>,
+}
+
+impl StopMachine {
+    fn new() -> Self {
+        StopMachine::default()
     }
 
-    parser.result
-}
\ No newline at end of file
+    fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
+        use svgparser::AttributeValue::{Color, PredefValue};
+        match (id, val) {
+            (AttributeId::Offset, AttributeValue::Number(num)) |
+            (AttributeId::Offset, AttributeValue::Percentage(num)) => {
+                self.offset = Some(num.max(0.).min(1.))
+            }
+            (AttributeId::StopColor, AttributeValue::Other(Color(color))) => {
+                self.color = Some(Some(color))
+            }
+            (AttributeId::StopColor, AttributeValue::Other(PredefValue(ValueId::CurrentColor))) => {
+                self.color = Some(None)
+            }
+            (AttributeId::StopOpacity, AttributeValue::Number(num)) => self.opacity = Some(num),
+            _ => {}
+        }
+    }
+
+    fn complete(self, current_color: svgparser::Color) -> (f32, Rgba) {
+        let color = match self.color {
+            Some(Some(color)) => color,
+            Some(None) => current_color,
+            None => svgparser::Color::new(0, 0, 0),
+        };
+        let mut color: Rgba = Srgb::new_u8(color.red, color.green, color.blue).to_linear();
+        color.alpha = self.opacity.unwrap_or(1.);
+        (self.offset.unwrap_or(0.), color)
+    }
+}
+
+#[derive(Debug, Default)]
+struct UseMachine {
+    href: Option<String>,
+    x: f32,
+    y: f32,
+    /// The size of the viewport of a referenced `<symbol>`.
+    width: Option<f32>,
+    height: Option<f32>,
+}
+
+impl UseMachine {
+    fn new() -> Self {
+        UseMachine::default()
+    }
+
+    fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
+        use svgparser::AttributeValue::IRI;
+        match (id, val) {
+            (AttributeId::XlinkHref, AttributeValue::Other(IRI(link))) => {
+                self.href = Some(link.to_string())
+            }
+            (AttributeId::X, AttributeValue::Number(num)) => self.x = num,
+            (AttributeId::Y, AttributeValue::Number(num)) => self.y = num,
+            (AttributeId::Width, AttributeValue::Number(num)) => self.width = Some(num),
+            (AttributeId::Height, AttributeValue::Number(num)) => self.height = Some(num),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClipPathMachine {
+    /// Whether the content is given relative to the bounding box of the clipped element.
+    object_bounding_box: bool,
+}
+
+impl ClipPathMachine {
+    fn new() -> Self {
+        ClipPathMachine::default()
+    }
+
+    fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
+        use svgparser::AttributeValue::PredefValue;
+        if let (AttributeId::ClipPathUnits, AttributeValue::Other(PredefValue(units))) = (id, val) {
+            self.object_bounding_box = units == ValueId::ObjectBoundingBox;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct MaskMachine {
+    x: Option<Coordinate>,
+    y: Option<Coordinate>,
+    width: Option<Coordinate>,
+    height: Option<Coordinate>,
+    /// Whether the region is given relative to the bounding box of the masked element.
+    object_bounding_box: bool,
+    /// Whether the content is given relative to the bounding box of the masked element.
+    content_object_bounding_box: bool,
+    luminance: bool,
+    /// The outline of the mask region, see `Mask`.
+    region: Vec<Segment>,
+}
+
+impl MaskMachine {
+    fn new() -> Self {
+        MaskMachine {
+            x: None,
+            y: None,
+            width: None,
+            height: None,
+            object_bounding_box: true,
+            content_object_bounding_box: false,
+            luminance: true,
+            region: Vec::new(),
+        }
+    }
+
+    fn attribute(&mut self, id: AttributeId, val: AttributeValue) {
+        use svgparser::AttributeValue::PredefValue;
+        match (id, val) {
+            (AttributeId::X, val) => self.x = Coordinate::from_attribute(val),
+            (AttributeId::Y, val) => self.y = Coordinate::from_attribute(val),
+            (AttributeId::Width, val) => self.width = Coordinate::from_attribute(val),
+            (AttributeId::Height, val) => self.height = Coordinate::from_attribute(val),
+            (AttributeId::MaskUnits, AttributeValue::Other(PredefValue(units))) => {
+                self.object_bounding_box = units == ValueId::ObjectBoundingBox
+            }
+            (AttributeId::MaskContentUnits, AttributeValue::Other(PredefValue(units))) => {
+                self.content_object_bounding_box = units == ValueId::ObjectBoundingBox
+            }
+            _ => {}
+        }
+    }
+
+    /// Sets the mask region for a masked element with the given bounding box in its user space,
+    /// where `transform` maps that space to the space of the paths.
+    ///
+    /// Returns the transformation of the content into the user space of the masked element, or
+    /// `None` if the content is invisible since the element has no bounding box.
+    fn complete(
+        &mut self,
+        bbox: Option<Rect>,
+        transform: Transform,
+        viewport: (f32, f32),
+    ) -> Option<Transform> {
+        // maps the unit square onto the bounding box
+        let bbox_units = match bbox {
+            Some(bbox) if bbox.size.width > 0. && bbox.size.height > 0. => {
+                Some(Transform::translate(bbox.origin.x, bbox.origin.y) *
+                    Transform::scale(bbox.size.width, bbox.size.height))
+            }
+            _ => None,
+        };
+        let (units, reference) = match (self.object_bounding_box, bbox_units) {
+            (false, _) => (Transform::identity(), viewport),
+            (true, Some(units)) => (units, (1., 1.)),
+            (true, None) => return None,
+        };
+        let resolve = |coordinate: Option<Coordinate>, default, reference| {
+            coordinate.unwrap_or(Coordinate::Fraction(default)).resolve(reference)
+        };
+        let x = resolve(self.x, -0.1, reference.0);
+        let y = resolve(self.y, -0.1, reference.1);
+        let width = resolve(self.width, 1.2, reference.0);
+        let height = resolve(self.height, 1.2, reference.1);
+        if width > 0. && height > 0. {
+            let mut builder = PathBuilder::new();
+            builder.move_to(Point::new(x, y));
+            builder.line_to(Point::new(x + width, y));
+            builder.line_to(Point::new(x + width, y + height));
+            builder.line_to(Point::new(x, y + height));
+            builder.close();
+            let transform = transform * units;
+            self.region = builder
+                .into_segments()
+                .iter()
+                .map(|segment| segment.transform(&transform))
+                .collect();
+        }
+
+        if self.content_object_bounding_box {
+            bbox_units
+        } else {
+            Some(Transform::identity())
+        }
+    }
+}
+
+/// The shapes of a clip path while its content is processed for a clipped element.
+#[derive(Debug)]
+struct ClipInstance {
+    /// The bounding box of the clipped element in its user space.
+    bbox: Option<Rect>,
+    shapes: Vec<(Vec<Segment>, FillRule)>,
+}
+
+/// A path whose paint refers to a paint server, which is resolved after parsing the whole
+/// document.
+#[derive(Debug)]
+struct PaintServerReference {
+    /// The index of the path in the result.
+    path: usize,
+    link: String,
+    fallback: Option<Paint>,
+    opacity: f32,
+    /// The bounding box of the element in its user space.
+    bbox: Rect,
+    /// Maps the user space of the element to the space of the path.
+    transform: Transform,
+}
+
+/// Converts the `d` attribute of a `<path>` element into closed outlines.
+///
+/// Parsing stops at the first error, keeping everything up to that point as the SVG
+/// specification demands.
+fn parse_path_data(data: &str) -> Vec<Subpath> {
+    let mut tokenizer = path::Tokenizer::from_str(data);
+    let mut builder = PathBuilder::new();
+
+    // the second control point of the previous segment, used to compute the reflected control
+    // point of smooth curves
+    let mut prev_cubic_control = None;
+    let mut prev_quadratic_control = None;
+
+    loop {
+        let token = match tokenizer.parse_next() {
+            Ok(path::Token::EndOfStream) | Err(_) => break,
+            Ok(token) => token,
+        };
+
+        let current = builder.current_point();
+        let point = |abs: bool, x: f64, y: f64| if abs {
+            Point::new(x as f32, y as f32)
+        } else {
+            current.offset(x as f32, y as f32)
+        };
+        let reflect = |control: Option<Point>| match control {
+            Some(control) => current + (current - control),
+            None => current,
+        };
+
+        let mut cubic_control = None;
+        let mut quadratic_control = None;
+        match token {
+            path::Token::MoveTo { abs, x, y } => builder.move_to(point(abs, x, y)),
+            path::Token::LineTo { abs, x, y } => builder.line_to(point(abs, x, y)),
+            path::Token::HorizontalLineTo { abs, x } => {
+                let x = if abs { x as f32 } else { current.x + x as f32 };
+                builder.line_to(Point::new(x, current.y));
+            }
+            path::Token::VerticalLineTo { abs, y } => {
+                let y = if abs { y as f32 } else { current.y + y as f32 };
+                builder.line_to(Point::new(current.x, y));
+            }
+            path::Token::CurveTo { abs, x1, y1, x2, y2, x, y } => {
+                let control2 = point(abs, x2, y2);
+                builder.cubic_to(point(abs, x1, y1), control2, point(abs, x, y));
+                cubic_control = Some(control2);
+            }
+            path::Token::SmoothCurveTo { abs, x2, y2, x, y } => {
+                let control2 = point(abs, x2, y2);
+                builder.cubic_to(reflect(prev_cubic_control), control2, point(abs, x, y));
+                cubic_control = Some(control2);
+            }
+            path::Token::Quadratic { abs, x1, y1, x, y } => {
+                let control = point(abs, x1, y1);
+                builder.quadratic_to(control, point(abs, x, y));
+                quadratic_control = Some(control);
+            }
+            path::Token::SmoothQuadratic { abs, x, y } => {
+                let control = reflect(prev_quadratic_control);
+                builder.quadratic_to(control, point(abs, x, y));
+                quadratic_control = Some(control);
+            }
+            path::Token::EllipticalArc {
+                abs,
+                rx,
+                ry,
+                x_axis_rotation,
+                large_arc,
+                sweep,
+                x,
+                y,
+            } => {
+                let end = point(abs, x, y);
+                let radii = Vec2d::new(rx as f32, ry as f32);
+                let rotation = (x_axis_rotation as f32).to_radians();
+                match arc_from_endpoints(current, end, radii, rotation, large_arc, sweep) {
+                    Some(arc) => builder.arc(&arc),
+                    None => builder.line_to(end),
+                }
+            }
+            path::Token::ClosePath { .. } => builder.close(),
+            path::Token::EndOfStream => unreachable!(),
+        }
+        prev_cubic_control = cubic_control;
+        prev_quadratic_control = quadratic_control;
+    }
+
+    builder.into_subpaths()
+}
+
+/// Converts an arc given in the endpoint parametrization of SVG into the center
+/// parametrization, following the SVG implementation notes (F.6.5 and F.6.6).
+///
+/// Returns `None` if the arc degenerates into a straight line.
+fn arc_from_endpoints(
+    start: Point,
+    end: Point,
+    radii: Vec2d,
+    x_rotation: f32,
+    large_arc: bool,
+    sweep: bool,
+) -> Option<EllipticalArc> {
+    use std::f32::consts::PI;
+
+    let (mut rx, mut ry) = (radii.x.abs(), radii.y.abs());
+    if start == end || rx == 0. || ry == 0. {
+        return None;
+    }
+
+    let (sin_phi, cos_phi) = x_rotation.sin_cos();
+    let half_diff = (start - end) / 2.;
+    let x1 = cos_phi * half_diff.x + sin_phi * half_diff.y;
+    let y1 = -sin_phi * half_diff.x + cos_phi * half_diff.y;
+
+    // scale up radii that are too small to connect both points
+    let lambda = (x1 * x1) / (rx * rx) + (y1 * y1) / (ry * ry);
+    if lambda > 1. {
+        rx *= lambda.sqrt();
+        ry *= lambda.sqrt();
+    }
+
+    let numerator = rx * rx * ry * ry - rx * rx * y1 * y1 - ry * ry * x1 * x1;
+    let denominator = rx * rx * y1 * y1 + ry * ry * x1 * x1;
+    let mut factor = (numerator / denominator).max(0.).sqrt();
+    if large_arc == sweep {
+        factor = -factor;
+    }
+    let cx1 = factor * rx * y1 / ry;
+    let cy1 = -factor * ry * x1 / rx;
+
+    let mid = start - half_diff;
+    let center = mid + Vec2d::new(cos_phi * cx1 - sin_phi * cy1, sin_phi * cx1 + cos_phi * cy1);
+
+    let start_angle = ((y1 - cy1) / ry).atan2((x1 - cx1) / rx);
+    let end_angle = ((-y1 - cy1) / ry).atan2((-x1 - cx1) / rx);
+    let mut sweep_angle = end_angle - start_angle;
+    if sweep && sweep_angle < 0. {
+        sweep_angle += 2. * PI;
+    } else if !sweep && sweep_angle > 0. {
+        sweep_angle -= 2. * PI;
+    }
+
+    Some(EllipticalArc {
+        center: center,
+        radii: Vec2d::new(rx, ry),
+        x_rotation: x_rotation,
+        start_angle: start_angle,
+        sweep_angle: sweep_angle,
+    })
+}
+
+/// A part of the document that is relevant for rendering.
+#[derive(Debug, Clone)]
+enum Event<'a> {
+    ElementStart(Option<ElementId>),
+    Attribute(AttributeId, TextFrame<'a>),
+    /// An attribute that is unknown to svgparser by its name and value.
+    XmlAttribute(&'a str, &'a str),
+    ElementEnd(ElementEnd<'a>),
+}
+
+#[derive(Debug, Default)]
+struct Parser<'a> {
+    result: VectorGraphic,
+    stack: Vec<ParserState>,
+    dpi: f32,
+    /// The size of the image if the document does not specify it.
+    default_size: (f32, f32),
+    /// The size of the viewport of the root element in user units.
+    viewport: (f32, f32),
+    /// The whole document, such that referenced elements can be processed again.
+    events: Vec<Event<'a>>,
+    /// Maps the ids of elements to the range of their events.
+    elements: HashMap<String, (usize, usize)>,
+    /// The start events of the elements that are currently instanced by `<use>`.
+    instances: Vec<usize>,
+    /// The number of events that have been processed again for references.
+    instanced_events: usize,
+    /// The clip path whose content is currently processed, if any.
+    clip: Option<ClipInstance>,
+    /// The mask that has been closed last.
+    completed_mask: Option<MaskMachine>,
+    /// The attributes of the current element, which are applied once all of them are known.
+    attributes: Vec<(AttributeId, TextFrame<'a>)>,
+    gradients: HashMap<String, GradientMachine>,
+    paint_servers: Vec<PaintServerReference>,
+}
+
+impl<'a> Parser<'a> {
+    /// Reads all events of the document and the ids of its elements.
+    fn read(&mut self, svg: &'a str) -> Result<(), SvgError> {
+        let mut tokenizer = Tokenizer::from_str(svg);
+        // the start events of the open elements and their ids
+        let mut open: Vec<(usize, Option<ElementId>, Option<String>)> = Vec::new();
+        loop {
+            let token = match tokenizer.parse_next() {
+                Ok(token) => token,
+                Err(err) => {
+                    let element = open.last().and_then(|&(_, id, _)| id);
+                    return Err(SvgError::new(SvgErrorKind::Syntax(err), element));
+                }
+            };
+            let event = match token {
+                Token::SvgElementStart(id) => Event::ElementStart(Some(id)),
+                Token::XmlElementStart(_) => Event::ElementStart(None),
+                Token::ElementEnd(end) => Event::ElementEnd(end),
+                Token::SvgAttribute(id, val) => Event::Attribute(id, val),
+                Token::XmlAttribute(name, val) => Event::XmlAttribute(name, val),
+                Token::EndOfStream => break,
+                _ => continue,
+            };
+            match event {
+                Event::ElementStart(id) => open.push((self.events.len(), id, None)),
+                Event::Attribute(AttributeId::Id, val) => {
+                    if let Some(&mut (_, _, ref mut element_id)) = open.last_mut() {
+                        *element_id = Some(val.slice().trim().to_string());
+                    }
+                }
+                Event::ElementEnd(ElementEnd::Open) |
+                Event::Attribute(..) |
+                Event::XmlAttribute(..) => {}
+                Event::ElementEnd(_) => {
+                    if let Some((start, _, Some(element_id))) = open.pop() {
+                        // the first element with a given id wins
+                        let end = self.events.len() + 1;
+                        self.elements.entry(element_id).or_insert((start, end));
+                    }
+                }
+            }
+            self.events.push(event);
+        }
+        Ok(())
+    }
+
+    /// Processes the events in the range from `start` to `end`.
+    fn process(&mut self, start: usize, end: usize) -> Result<(), SvgError> {
+        for index in start..end {
+            match self.events[index].clone() {
+                Event::ElementStart(id) => self.element_start(id, index),
+                Event::Attribute(id, val) => self.attribute(id, val),
+                Event::XmlAttribute(name, val) => self.xml_attribute(name, val),
+                Event::ElementEnd(end) => self.element_end(end)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Opens the element `id`, whose start event has the index `index`.
+    fn element_start(&mut self, id: Option<ElementId>, index: usize) {
+        let elem = match id {
+            Some(ElementId::Svg) => Some(Element::Svg(SvgRootMachine::new())),
+            Some(ElementId::Line) => Some(Element::Line(LineMachine::new())),
+            Some(ElementId::Polygon) => Some(Element::Polygon(PolygonMachine::new(true))),
+            Some(ElementId::Polyline) => Some(Element::Polyline(PolygonMachine::new(false))),
+            Some(ElementId::Rect) => Some(Element::Rect(RectMachine::new())),
+            Some(ElementId::Circle) => Some(Element::Circle(EllipseMachine::new())),
+            Some(ElementId::Ellipse) => Some(Element::Ellipse(EllipseMachine::new())),
+            Some(ElementId::Path) => Some(Element::Path(PathMachine::new())),
+            Some(ElementId::LinearGradient) => {
+                Some(Element::LinearGradient(GradientMachine::new(false)))
+            }
+            Some(ElementId::RadialGradient) => {
+                Some(Element::RadialGradient(GradientMachine::new(true)))
+            }
+            Some(ElementId::Stop) => Some(Element::Stop(StopMachine::new())),
+            Some(ElementId::Use) => Some(Element::Use(UseMachine::new())),
+            Some(ElementId::Symbol) => Some(Element::Symbol(SvgRootMachine::new())),
+            Some(ElementId::ClipPath) => Some(Element::ClipPath(ClipPathMachine::new())),
+            Some(ElementId::Mask) => Some(Element::Mask(MaskMachine::new())),
+            _ => None,
+        };
+        // the content of `<defs>` is only rendered through references, just like a symbol that
+        // is not instanced by `<use>`
+        let hidden = match id {
+            Some(ElementId::Defs) => true,
+            Some(ElementId::Symbol) => self.state().elem_id != Some(ElementId::Use),
+            Some(ElementId::ClipPath) => self.clip.is_none(),
+            Some(ElementId::Mask) => true,
+            _ => false,
+        };
+        // every element gets its own state, so that the stack stays balanced with the
+        // closing tags
+        let parent = self.state().clone_inherited();
+        self.stack.push(ParserState {
+            elem: elem,
+            elem_id: id,
+            start: Some(index),
+            hidden: parent.hidden || hidden,
+            first_path: self.result.paths.len(),
+            ..parent
+        });
+    }
+
+    fn attribute(&mut self, id: AttributeId, val: TextFrame<'a>) {
+        self.attributes.push((id, val));
+    }
+
+    /// Handles attributes and style declarations that svgparser does not know.
+    fn xml_attribute(&mut self, name: &str, val: &str) {
+        let state = self.state();
+        match name {
+            "mask-type" => {
+                if let Some(Element::Mask(ref mut mach)) = state.elem {
+                    mach.luminance = val.trim() != "alpha";
+                }
+            }
+            "mix-blend-mode" => {
+                if let Some(mode) = BlendMode::from_name(val.trim()) {
+                    state.blend_mode = mode;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn apply_attributes(&mut self) -> Result<(), SvgError> {
+        let mut attributes = mem::replace(&mut self.attributes, Vec::new());
+        // declarations in the style attribute take precedence over presentation attributes, so
+        // they are applied last
+        let mut properties = Vec::new();
+        if let Some(index) = attributes.iter().position(|&(id, _)| id == AttributeId::Style) {
+            let (_, style) = attributes.remove(index);
+            let (declarations, unknown) = parse_style(style);
+            attributes.extend(declarations);
+            properties = unknown;
+        }
+        // lengths in `em` and `ex` refer to the font size of the element itself
+        attributes.sort_by_key(|&(id, _)| id != AttributeId::FontSize);
+
+        let dpi = self.dpi;
+        for (id, val) in attributes {
+            if val.slice().trim() == "inherit" {
+                self.inherit_property(id);
+            } else if let Err(err) = self.state().attribute(id, val, dpi) {
+                let kind = SvgErrorKind::InvalidAttribute(id.name().to_string(), err);
+                return Err(self.error(kind));
+            }
+        }
+        for (name, val) in properties {
+            self.xml_attribute(name, val);
+        }
+        Ok(())
+    }
+
+    /// Returns an error with the innermost open element as its context.
+    fn error(&self, kind: SvgErrorKind) -> SvgError {
+        let element = self.stack.last().and_then(|state| state.elem_id);
+        SvgError::new(kind, element)
+    }
+
+    /// Sets the property `id` of the current element to the value of its parent.
+    fn inherit_property(&mut self, id: AttributeId) {
+        let len = self.stack.len();
+        if len < 2 {
+            return;
+        }
+        let (parents, current) = self.stack.split_at_mut(len - 1);
+        current[0].inherit_property(id, &parents[len - 2]);
+    }
+
+    fn element_end(&mut self, end: ElementEnd) -> Result<(), SvgError> {
+        match end {
+            ElementEnd::Open => {
+                self.apply_attributes()?;
+                self.complete_element()?;
+            }
+            ElementEnd::Empty => {
+                self.apply_attributes()?;
+                self.complete_element()?;
+                self.close_element()?;
+            }
+            ElementEnd::CloseXml(_) |
+            ElementEnd::CloseSvg(_) => self.close_element()?,
+        }
+        Ok(())
+    }
+
+    fn close_element(&mut self) -> Result<(), SvgError> {
+        if self.state().clip_path.is_some() {
+            self.clip_element()?;
+        }
+        let needs_group = {
+            let state = self.state();
+            state.mask.is_some() || state.opacity < 1. || state.blend_mode != BlendMode::Normal
+        };
+        if needs_group {
+            self.group_element()?;
+        }
+        let state = match self.stack.pop() {
+            Some(state) => state,
+            None => return Ok(()),
+        };
+        // the bounding box of a group contains those of its children
+        if let Some(bbox) = state.bbox {
+            let parent = self.state();
+            if let Some(inverse) = parent.transform.inverse() {
+                let bbox = bbox.transform(&(inverse * state.transform));
+                parent.bbox = Some(parent.bbox.map_or(bbox, |parent_bbox| parent_bbox.union(bbox)));
+            }
+        }
+        match state.elem {
+            Some(Element::LinearGradient(mach)) |
+            Some(Element::RadialGradient(mach)) => {
+                if let Some(id) = mach.id.clone() {
+                    self.gradients.insert(id, mach);
+                }
+            }
+            Some(Element::Mask(mach)) => self.completed_mask = Some(mach),
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Puts the paths of the current element into a group that is composited with the opacity
+    /// and the blend mode of the element and modulated by the mask it refers to.
+    fn group_element(&mut self) -> Result<(), SvgError> {
+        let (first_path, opacity, blend_mode) = {
+            let state = self.state();
+            (state.first_path, state.opacity.max(0.).min(1.), state.blend_mode)
+        };
+        let end = self.result.paths.len();
+        if self.clip.is_some() || self.state().hidden || first_path == end {
+            return Ok(());
+        }
+        let mask = self.mask_element()?;
+        if mask.is_some() || opacity < 1. || blend_mode != BlendMode::Normal {
+            self.result.groups.push(Group {
+                start: first_path,
+                end: end,
+                opacity: opacity,
+                blend_mode: blend_mode,
+                mask: mask,
+            });
+        }
+        Ok(())
+    }
+
+    /// Adds the content of the mask that the current element refers to to the result and
+    /// returns its index.
+    ///
+    /// Masks that would be instanced inside of themselves are ignored.
+    fn mask_element(&mut self) -> Result<Option<usize>, SvgError> {
+        let link = self.state().mask.take();
+        let range = link.and_then(|link| self.elements.get(&link).cloned());
+        let (start, end) = match range {
+            Some(range) => range,
+            None => return Ok(None),
+        };
+        match self.events[start] {
+            Event::ElementStart(Some(ElementId::Mask)) => {}
+            _ => return Ok(None),
+        }
+        if self.instances.contains(&start) || self.instances.len() >= MAX_REFERENCE_DEPTH {
+            return Ok(None);
+        }
+        self.count_instance(start, end, ElementId::Mask)?;
+        let content_start = self.result.paths.len();
+
+        // the mask element is hidden in its original place, but not here
+        self.instances.push(start);
+        self.completed_mask = None;
+        self.element_start(Some(ElementId::Mask), start);
+        self.state().hidden = false;
+        let result = self.process(start + 1, end);
+        self.instances.pop();
+        result?;
+
+        let mach = match self.completed_mask.take() {
+            Some(mach) => mach,
+            None => return Ok(None),
+        };
+        self.result.masks.push(Mask {
+            start: content_start,
+            end: self.result.paths.len(),
+            region: mach.region,
+            luminance: mach.luminance,
+        });
+        Ok(Some(self.result.masks.len() - 1))
+    }
+
+    /// Adds the clip path that the current element refers to to all of its paths.
+    ///
+    /// References to anything but a `<clipPath>` are ignored, just like clip paths of the content
+    /// of clip paths.
+    fn clip_element(&mut self) -> Result<(), SvgError> {
+        let (link, bbox, first_path) = {
+            let state = self.state();
+            (state.clip_path.take(), state.bbox, state.first_path)
+        };
+        let range = link.and_then(|link| self.elements.get(&link).cloned());
+        let (start, end) = match range {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+        match self.events[start] {
+            Event::ElementStart(Some(ElementId::ClipPath)) => {}
+            _ => return Ok(()),
+        }
+        if self.clip.is_some() || self.state().hidden {
+            return Ok(());
+        }
+        self.count_instance(start, end, ElementId::ClipPath)?;
+
+        self.clip = Some(ClipInstance {
+            bbox: bbox,
+            shapes: Vec::new(),
+        });
+        let result = self.process(start, end);
+        let shapes = self.clip.take().map_or(Vec::new(), |clip| clip.shapes);
+        result?;
+
+        let index = self.result.clip_paths.len();
+        self.result.clip_paths.push(ClipPath { shapes: shapes });
+        for path in &mut self.result.paths[first_path..] {
+            path.clip_paths.push(index);
+        }
+        Ok(())
+    }
+
+    /// Called once all attributes of the current element have been read.
+    fn complete_element(&mut self) -> Result<(), SvgError> {
+        let elem = match self.state().elem.take() {
+            Some(elem) => elem,
+            None => return Ok(()),
+        };
+        let mut subpaths = Vec::new();
+        // lines have no interior that could be filled
+        let is_fillable = match elem {
+            Element::Svg(mach) => {
+                let (size, transform, viewport) = mach.complete(self.default_size);
+                self.result.size = size;
+                self.result.transform = transform;
+                self.viewport = viewport;
+                self.state().viewport = viewport;
+                return Ok(());
+            }
+            // gradients are completed by their closing tag, after all stops have been read
+            gradient @ Element::LinearGradient(_) |
+            gradient @ Element::RadialGradient(_) => {
+                self.state().elem = Some(gradient);
+                return Ok(());
+            }
+            Element::Stop(mach) => {
+                let stop = mach.complete(self.state().color);
+                let parent = self.stack.len() - 2;
+                match self.stack[parent].elem {
+                    Some(Element::LinearGradient(ref mut gradient)) |
+                    Some(Element::RadialGradient(ref mut gradient)) => gradient.stops.push(stop),
+                    _ => {}
+                }
+                return Ok(());
+            }
+            Element::Use(mach) => return self.instantiate(mach),
+            Element::Symbol(mach) => {
+                // the viewport of a symbol is set by the `<use>` element that instances it
+                if let Some(view_box) = mach.view_box {
+                    let state = self.state();
+                    let ratio = mach.preserve_aspect_ratio;
+                    state.transform = state.transform *
+                        ratio.view_box_transform(view_box, state.viewport);
+                    state.viewport = (view_box.size.width, view_box.size.height);
+                }
+                return Ok(());
+            }
+            Element::Mask(mut mach) => {
+                // the masked element is the parent of the mask while it is instanced
+                let len = self.stack.len();
+                let bbox = self.stack[len - 2].bbox;
+                let state = &mut self.stack[len - 1];
+                match mach.complete(bbox, state.transform, state.viewport) {
+                    Some(units) => state.transform = state.transform * units,
+                    None => state.hidden = true,
+                }
+                // kept until the closing tag, after all content has been read
+                state.elem = Some(Element::Mask(mach));
+                return Ok(());
+            }
+            Element::ClipPath(mach) => {
+                if mach.object_bounding_box {
+                    let bbox = self.clip.as_ref().and_then(|clip| clip.bbox);
+                    let state = self.state();
+                    match bbox {
+                        Some(bbox) => {
+                            let units = Transform::translate(bbox.origin.x, bbox.origin.y) *
+                                Transform::scale(bbox.size.width, bbox.size.height);
+                            state.transform = state.transform * units;
+                        }
+                        // an element without a bounding box is clipped entirely
+                        None => state.hidden = true,
+                    }
+                }
+                return Ok(());
+            }
+            Element::Line(mach) => {
+                mach.complete(&mut subpaths);
+                false
+            }
+            Element::Polygon(mach) |
+            Element::Polyline(mach) => {
+                mach.complete(&mut subpaths);
+                true
+            }
+            Element::Rect(mach) => {
+                mach.complete(&mut subpaths);
+                true
+            }
+            Element::Circle(mach) |
+            Element::Ellipse(mach) => {
+                mach.complete(&mut subpaths);
+                true
+            }
+            Element::Path(mach) => {
+                mach.complete(&mut subpaths);
+                true
+            }
+        };
+        if subpaths.is_empty() || self.state().hidden {
+            return Ok(());
+        }
+
+        let fill_segments = subpaths
+            .iter()
+            .cloned()
+            .flat_map(Subpath::into_closed_segments)
+            .collect::<Vec<_>>();
+        // the bounding box of the geometry is also used for the stroke
+        let bbox = rasterizer::bounding_box(&fill_segments);
+        self.state().bbox = Some(bbox);
+
+        // the content of clip paths only contributes its geometry
+        let (transform, clip_rule) = {
+            let state = self.state();
+            (state.transform, state.clip_rule)
+        };
+        if let Some(ref mut clip) = self.clip {
+            let segments = fill_segments
+                .iter()
+                .map(|segment| segment.transform(&transform))
+                .collect();
+            clip.shapes.push((segments, clip_rule));
+            return Ok(());
+        }
+        let (fill, fill_opacity, fill_rule) = {
+            let state = self.state();
+            (state.fill.clone(), state.fill_opacity, state.fill_rule)
+        };
+        let (stroke, stroke_opacity, stroke_style, dash_array, dash_offset) = {
+            let state = self.state();
+            (
+                state.stroke.clone(),
+                state.stroke_opacity,
+                state.stroke_style,
+                state.stroke_dasharray.clone(),
+                state.stroke_dashoffset,
+            )
+        };
+        // a single path needs no layer of its own to apply the opacity of the element
+        let opacity = {
+            let state = self.state();
+            if state.mask.is_some() || is_fillable && fill != PaintValue::None &&
+                stroke != PaintValue::None
+            {
+                1.
+            } else {
+                mem::replace(&mut state.opacity, 1.).max(0.).min(1.)
+            }
+        };
+        let (fill_opacity, stroke_opacity) = (fill_opacity * opacity, stroke_opacity * opacity);
+        if is_fillable {
+            self.add_path(fill_segments, fill_rule, &fill, fill_opacity, bbox);
+        }
+        if stroke != PaintValue::None {
+            let transform = self.result.transform * self.state().transform;
+            let tolerance = STROKE_TOLERANCE / transform.determinant().abs().sqrt();
+            let dashes = stroke::dash(&subpaths, &dash_array, dash_offset, tolerance);
+            let outline = stroke::stroke(&dashes, &stroke_style, tolerance);
+            self.add_path(outline, FillRule::NonZero, &stroke, stroke_opacity, bbox);
+        }
+        Ok(())
+    }
+
+    /// Processes the element referenced by a `<use>` element as its child.
+    ///
+    /// Missing references and references that would instance an element inside of itself,
+    /// directly or through other references, are ignored.
+    fn instantiate(&mut self, mach: UseMachine) -> Result<(), SvgError> {
+        let range = mach.href.and_then(|id| self.elements.get(&id).cloned());
+        let (start, end) = match range {
+            Some(range) => range,
+            None => return Ok(()),
+        };
+        // a reference to one of the open elements, which include the instanced ones, is circular
+        let circular = self.stack.iter().any(|state| state.start == Some(start));
+        if self.state().hidden || circular || self.instances.len() >= MAX_REFERENCE_DEPTH {
+            return Ok(());
+        }
+        let is_symbol = match self.events[start] {
+            Event::ElementStart(Some(ElementId::Symbol)) => true,
+            _ => false,
+        };
+        {
+            let state = self.state();
+            state.transform = state.transform * Transform::translate(mach.x, mach.y);
+            // the size defaults to `100%` and only applies to symbols
+            if is_symbol {
+                let (width, height) = state.viewport;
+                state.viewport = (mach.width.unwrap_or(width), mach.height.unwrap_or(height));
+            }
+        }
+        self.count_instance(start, end, ElementId::Use)?;
+        self.instances.push(start);
+        let result = self.process(start, end);
+        self.instances.pop();
+        result
+    }
+
+    /// Adds the events `start..end`, which are about to be processed again for a reference by
+    /// `element`, to the number of instanced events.
+    ///
+    /// Returns an error once there are more than `MAX_INSTANCED_EVENTS`.
+    fn count_instance(
+        &mut self,
+        start: usize,
+        end: usize,
+        element: ElementId,
+    ) -> Result<(), SvgError> {
+        self.instanced_events += end - start;
+        if self.instanced_events > MAX_INSTANCED_EVENTS {
+            return Err(SvgError::new(SvgErrorKind::TooManyInstances, Some(element)));
+        }
+        Ok(())
+    }
+
+    /// Adds the area enclosed by `segments` in the user space of the current element to the
+    /// result, unless `paint` is `none`.
+    fn add_path(
+        &mut self,
+        segments: Vec<Segment>,
+        fill_rule: FillRule,
+        paint: &PaintValue,
+        opacity: f32,
+        bbox: Rect,
+    ) {
+        let current_state = self.stack.last().unwrap();
+        let transform = current_state.transform;
+        let paint = match *paint {
+            PaintValue::Server(ref link, ref fallback) => {
+                self.paint_servers.push(PaintServerReference {
+                    path: self.result.paths.len(),
+                    link: link.clone(),
+                    fallback: current_state.color_paint(fallback, opacity),
+                    opacity: opacity,
+                    bbox: bbox,
+                    transform: transform,
+                });
+                // replaced once the whole document has been parsed
+                Paint::Color(Rgba::new(0., 0., 0., 0.))
+            }
+            ref paint => {
+                match current_state.color_paint(paint, opacity) {
+                    Some(paint) => paint,
+                    None => return,
+                }
+            }
+        };
+        let segments = segments.iter().map(|segment| segment.transform(&transform)).collect();
+        self.result.paths.push(Path {
+            segments: segments,
+            fill_rule: fill_rule,
+            paint: paint,
+            clip_paths: Vec::new(),
+        });
+    }
+
+    /// Returns the gradient with the given id with all attributes taken from the referenced
+    /// gradients.
+    fn gradient(&self, id: &str) -> Option<GradientMachine> {
+        let mut gradient = match self.gradients.get(id) {
+            Some(gradient) => gradient.clone(),
+            None => return None,
+        };
+        let mut href = gradient.href.clone();
+        for _ in 0..MAX_REFERENCE_DEPTH {
+            let template = match href.and_then(|link| self.gradients.get(&link)) {
+                Some(template) => template,
+                None => break,
+            };
+            gradient.inherit(template);
+            href = template.href.clone();
+        }
+        Some(gradient)
+    }
+
+    /// Replaces the paints of all paths that refer to a paint server. Paths whose paint cannot
+    /// be resolved are removed.
+    fn resolve_paint_servers(&mut self) {
+        let viewport = self.viewport;
+        let paints = self.paint_servers
+            .iter()
+            .map(|server| match self.gradient(&server.link) {
+                Some(gradient) => {
+                    gradient.to_paint(server.bbox, server.transform, server.opacity, viewport)
+                }
+                None => server.fallback.clone(),
+            })
+            .collect::<Vec<_>>();
+
+        let mut unpainted = Vec::new();
+        for (server, paint) in self.paint_servers.iter().zip(paints.into_iter()) {
+            match paint {
+                Some(paint) => self.result.paths[server.path].paint = paint,
+                None => unpainted.push(server.path),
+            }
+        }
+        let mut index = 0;
+        self.result.paths.retain(|_| {
+            index += 1;
+            !unpainted.contains(&(index - 1))
+        });
+        // the ranges of groups and masks shrink by the removed paths
+        let shift = |index: usize| index - unpainted.iter().filter(|&&path| path < index).count();
+        for group in &mut self.result.groups {
+            group.start = shift(group.start);
+            group.end = shift(group.end);
+        }
+        for mask in &mut self.result.masks {
+            mask.start = shift(mask.start);
+            mask.end = shift(mask.end);
+        }
+        // groups without paths have nothing to composite
+        self.result.groups.retain(|group| group.start < group.end);
+    }
+
+    fn state(&mut self) -> &mut ParserState {
+        self.stack.last_mut().unwrap()
+    }
+}
+
+#[derive(Debug)]
+struct ParserState {
+    /// The element the parser is currently processing, if any.
+    elem: Option<Element>,
+    /// The id of the current element, `None` for elements unknown to svgparser.
+    elem_id: Option<ElementId>,
+    /// The index of the start event of the current element, `None` outside of the root.
+    start: Option<usize>,
+    /// Maps the user space of the current element to the user space of the root element.
+    transform: Transform,
+    fill_rule: FillRule,
+    fill: PaintValue,
+    stroke: PaintValue,
+    /// The value of the `color` property that `currentColor` refers to.
+    color: svgparser::Color,
+    fill_opacity: f32,
+    stroke_opacity: f32,
+    stroke_style: StrokeStyle,
+    /// The lengths of dashes and gaps, empty for solid strokes.
+    stroke_dasharray: Vec<f32>,
+    stroke_dashoffset: f32,
+    /// The opacity of the current element, which applies to all of its paths as a whole.
+    opacity: f32,
+    /// The `mix-blend-mode` of the current element, which is not inherited.
+    blend_mode: BlendMode,
+    /// The size of the nearest viewport in user units, which percentages refer to.
+    viewport: (f32, f32),
+    font_size: f32,
+    /// Whether the element is only rendered through references.
+    hidden: bool,
+    clip_rule: FillRule,
+    /// The id of the clip path of the current element, which is not inherited.
+    clip_path: Option<String>,
+    /// The id of the mask of the current element, which is not inherited either.
+    mask: Option<String>,
+    /// The index of the first path that belongs to the current element.
+    first_path: usize,
+    /// The bounding box of the geometry of the current element in its user space.
+    bbox: Option<Rect>,
+}
+
+impl Default for ParserState {
+    fn default() -> ParserState {
+        ParserState {
+            elem: None,
+            elem_id: None,
+            start: None,
+            transform: Transform::identity(),
+            fill_rule: FillRule::default(),
+            fill: PaintValue::Color(svgparser::Color::new(0, 0, 0)),
+            stroke: PaintValue::None,
+            color: svgparser::Color::new(0, 0, 0),
+            fill_opacity: 1.,
+            stroke_opacity: 1.,
+            stroke_style: StrokeStyle::default(),
+            stroke_dasharray: Vec::new(),
+            stroke_dashoffset: 0.,
+            opacity: 1.,
+            blend_mode: BlendMode::Normal,
+            viewport: (0., 0.),
+            font_size: MEDIUM_FONT_SIZE,
+            hidden: false,
+            clip_rule: FillRule::default(),
+            clip_path: None,
+            mask: None,
+            first_path: 0,
+            bbox: None,
+        }
+    }
+}
+
+impl ParserState {
+    /// Returns a state for a child element, carrying over everything that is inherited.
+    fn clone_inherited(&self) -> ParserState {
+        ParserState {
+            elem: None,
+            elem_id: None,
+            start: None,
+            transform: self.transform,
+            fill_rule: self.fill_rule,
+            fill: self.fill.clone(),
+            stroke: self.stroke.clone(),
+            color: self.color,
+            fill_opacity: self.fill_opacity,
+            stroke_opacity: self.stroke_opacity,
+            stroke_style: self.stroke_style,
+            stroke_dasharray: self.stroke_dasharray.clone(),
+            stroke_dashoffset: self.stroke_dashoffset,
+            opacity: 1.,
+            blend_mode: BlendMode::Normal,
+            viewport: self.viewport,
+            font_size: self.font_size,
+            hidden: self.hidden,
+            clip_rule: self.clip_rule,
+            clip_path: None,
+            mask: None,
+            first_path: self.first_path,
+            bbox: None,
+        }
+    }
+
+    /// Copies the property `id` from `parent`, as requested by the value `inherit`.
+    fn inherit_property(&mut self, id: AttributeId, parent: &ParserState) {
+        match id {
+            AttributeId::FillRule => self.fill_rule = parent.fill_rule,
+            AttributeId::Fill => self.fill = parent.fill.clone(),
+            AttributeId::Stroke => self.stroke = parent.stroke.clone(),
+            AttributeId::Color => self.color = parent.color,
+            AttributeId::FillOpacity => self.fill_opacity = parent.fill_opacity,
+            AttributeId::StrokeOpacity => self.stroke_opacity = parent.stroke_opacity,
+            AttributeId::StrokeWidth => self.stroke_style.width = parent.stroke_style.width,
+            AttributeId::StrokeLinejoin => self.stroke_style.join = parent.stroke_style.join,
+            AttributeId::StrokeLinecap => self.stroke_style.cap = parent.stroke_style.cap,
+            AttributeId::StrokeMiterlimit => {
+                self.stroke_style.miter_limit = parent.stroke_style.miter_limit
+            }
+            AttributeId::StrokeDasharray => {
+                self.stroke_dasharray = parent.stroke_dasharray.clone()
+            }
+            AttributeId::StrokeDashoffset => self.stroke_dashoffset = parent.stroke_dashoffset,
+            AttributeId::Opacity => self.opacity = parent.opacity,
+            AttributeId::FontSize => self.font_size = parent.font_size,
+            AttributeId::ClipRule => self.clip_rule = parent.clip_rule,
+            AttributeId::ClipPath => self.clip_path = parent.clip_path.clone(),
+            AttributeId::Mask => self.mask = parent.mask.clone(),
+            _ => {}
+        }
+    }
+
+    /// Returns the color of `paint` with the given opacity, or `None` if it is not a color.
+    fn color_paint(&self, paint: &PaintValue, opacity: f32) -> Option<Paint> {
+        let color = match *paint {
+            PaintValue::None | PaintValue::Server(..) => return None,
+            PaintValue::CurrentColor => self.color,
+            PaintValue::Color(color) => color,
+        };
+        let mut color: Rgba = Srgb::new_u8(color.red, color.green, color.blue).to_linear();
+        color.alpha = opacity;
+        Some(Paint::Color(color))
+    }
+
+    /// Applies an attribute of the current element. Invalid values of presentation attributes
+    /// are ignored, other invalid values are reported.
+    fn attribute(
+        &mut self,
+        attr_id: AttributeId,
+        val: TextFrame,
+        dpi: f32,
+    ) -> Result<(), svgparser::Error> {
+        let elem_id = match self.elem_id {
+            Some(elem_id) => elem_id,
+            None => return Ok(()),
+        };
+        match attr_id {
+            AttributeId::Transform => {
+                self.transform = self.transform * parse_transform(val);
+                return Ok(());
+            }
+            AttributeId::GradientTransform => {
+                if let Some(ref mut elem) = self.elem {
+                    elem.svg_attribute(attr_id, AttributeValue::Transform(parse_transform(val)));
+                }
+                return Ok(());
+            }
+            AttributeId::FillRule |
+            AttributeId::Fill |
+            AttributeId::Stroke |
+            AttributeId::Color |
+            AttributeId::FillOpacity |
+            AttributeId::StrokeOpacity |
+            AttributeId::StrokeWidth |
+            AttributeId::StrokeLinejoin |
+            AttributeId::StrokeLinecap |
+            AttributeId::StrokeMiterlimit |
+            AttributeId::StrokeDasharray |
+            AttributeId::StrokeDashoffset |
+            AttributeId::FontSize |
+            AttributeId::ClipRule |
+            AttributeId::ClipPath |
+            AttributeId::Mask |
+            AttributeId::Opacity => {
+                if let Ok(val) = svgparser::AttributeValue::from_frame(elem_id, attr_id, val) {
+                    self.presentation_attribute(attr_id, val, dpi);
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        if self.elem.is_none() {
+            return Ok(());
+        }
+        let val = svgparser::AttributeValue::from_frame(elem_id, attr_id, val)?;
+        match val {
+            svgparser::AttributeValue::Number(num) => {
+                let val = AttributeValue::Number(num as f32);
+                self.elem.as_mut().unwrap().svg_attribute(attr_id, val)
+            }
+            svgparser::AttributeValue::NumberList(numbers) => {
+                let numbers = numbers.collect::<Result<Vec<_>, _>>()?;
+                let mut iter = numbers.into_iter().map(|x| x as f32);
+                let val = AttributeValue::NumberList(&mut iter);
+                self.elem.as_mut().unwrap().svg_attribute(attr_id, val)
+            }
+            svgparser::AttributeValue::Length(length) => {
+                let keeps_percentages = self.elem.as_ref().unwrap().keeps_percentages();
+                let val = if length.unit == LengthUnit::Percent && keeps_percentages {
+                    AttributeValue::Percentage(length.num as f32 / 100.)
+                } else {
+                    AttributeValue::Number(self.user_length(attr_id, length, dpi))
+                };
+                self.elem.as_mut().unwrap().svg_attribute(attr_id, val)
+            }
+            other => {
+                let val = AttributeValue::Other(other);
+                self.elem.as_mut().unwrap().svg_attribute(attr_id, val)
+            }
+        };
+        Ok(())
+    }
+
+    /// Converts the length of the attribute `attr_id` into user units.
+    fn user_length(&self, attr_id: AttributeId, length: Length, dpi: f32) -> f32 {
+        let factor = match length.unit {
+            LengthUnit::None | LengthUnit::Px => 1.,
+            LengthUnit::In => dpi,
+            LengthUnit::Cm => dpi / 2.54,
+            LengthUnit::Mm => dpi / 25.4,
+            LengthUnit::Pt => dpi / 72.,
+            LengthUnit::Pc => dpi / 6.,
+            LengthUnit::Em => self.font_size,
+            // without font metrics the x-height is taken to be half of the font size
+            LengthUnit::Ex => self.font_size / 2.,
+            LengthUnit::Percent => self.percentage_reference(attr_id) / 100.,
+        };
+        length.num as f32 * factor
+    }
+
+    /// Returns the length that a percentage in the attribute `attr_id` refers to.
+    fn percentage_reference(&self, attr_id: AttributeId) -> f32 {
+        let (width, height) = self.viewport;
+        match attr_id {
+            AttributeId::X | AttributeId::X1 | AttributeId::X2 | AttributeId::Cx |
+            AttributeId::Rx | AttributeId::Dx | AttributeId::Width => width,
+            AttributeId::Y | AttributeId::Y1 | AttributeId::Y2 | AttributeId::Cy |
+            AttributeId::Ry | AttributeId::Dy | AttributeId::Height => height,
+            AttributeId::FontSize => self.font_size,
+            // the diagonal normalized such that it equals the side of a square viewport
+            _ => ((width * width + height * height) / 2.).sqrt(),
+        }
+    }
+
+    /// Sets a property for this element and its descendants. Invalid values are ignored.
+    fn presentation_attribute(
+        &mut self,
+        id: AttributeId,
+        val: svgparser::AttributeValue,
+        dpi: f32,
+    ) {
+        use svgparser::AttributeValue::{Number, PredefValue, Color};
+        match (id, val) {
+            (AttributeId::FillRule, PredefValue(ValueId::Nonzero)) => {
+                self.fill_rule = FillRule::NonZero
+            }
+            (AttributeId::FillRule, PredefValue(ValueId::Evenodd)) => {
+                self.fill_rule = FillRule::EvenOdd
+            }
+            (AttributeId::ClipRule, PredefValue(ValueId::Nonzero)) => {
+                self.clip_rule = FillRule::NonZero
+            }
+            (AttributeId::ClipRule, PredefValue(ValueId::Evenodd)) => {
+                self.clip_rule = FillRule::EvenOdd
+            }
+            (AttributeId::ClipPath, PredefValue(ValueId::None)) => self.clip_path = None,
+            (AttributeId::ClipPath, svgparser::AttributeValue::FuncIRI(link)) => {
+                self.clip_path = Some(link.to_string())
+            }
+            (AttributeId::Mask, PredefValue(ValueId::None)) => self.mask = None,
+            (AttributeId::Mask, svgparser::AttributeValue::FuncIRI(link)) => {
+                self.mask = Some(link.to_string())
+            }
+            (AttributeId::Fill, val) => {
+                if let Some(paint) = PaintValue::from_attribute(val) {
+                    self.fill = paint;
+                }
+            }
+            (AttributeId::Stroke, val) => {
+                if let Some(paint) = PaintValue::from_attribute(val) {
+                    self.stroke = paint;
+                }
+            }
+            (AttributeId::Color, Color(color)) => self.color = color,
+            (AttributeId::FillOpacity, Number(num)) => self.fill_opacity = num as f32,
+            (AttributeId::StrokeOpacity, Number(num)) => self.stroke_opacity = num as f32,
+            (AttributeId::StrokeWidth, svgparser::AttributeValue::Length(length)) => {
+                let width = self.user_length(id, length, dpi);
+                if width >= 0. {
+                    self.stroke_style.width = width;
+                }
+            }
+            (AttributeId::StrokeLinejoin, PredefValue(join)) => {
+                match join {
+                    ValueId::Miter => self.stroke_style.join = LineJoin::Miter,
+                    ValueId::Round => self.stroke_style.join = LineJoin::Round,
+                    ValueId::Bevel => self.stroke_style.join = LineJoin::Bevel,
+                    _ => {}
+                }
+            }
+            (AttributeId::StrokeLinecap, PredefValue(cap)) => {
+                match cap {
+                    ValueId::Butt => self.stroke_style.cap = LineCap::Butt,
+                    ValueId::Round => self.stroke_style.cap = LineCap::Round,
+                    ValueId::Square => self.stroke_style.cap = LineCap::Square,
+                    _ => {}
+                }
+            }
+            (AttributeId::StrokeDasharray, PredefValue(ValueId::None)) => {
+                self.stroke_dasharray.clear()
+            }
+            (AttributeId::StrokeDasharray, svgparser::AttributeValue::LengthList(list)) => {
+                let lengths = list
+                    .map(|length| length.map(|length| self.user_length(id, length, dpi)))
+                    .collect::<Result<Vec<_>, _>>();
+                if let Ok(lengths) = lengths {
+                    self.stroke_dasharray = lengths;
+                }
+            }
+            (AttributeId::StrokeDashoffset, svgparser::AttributeValue::Length(length)) => {
+                self.stroke_dashoffset = self.user_length(id, length, dpi)
+            }
+            (AttributeId::FontSize, svgparser::AttributeValue::Length(length)) => {
+                let font_size = self.user_length(id, length, dpi);
+                if font_size >= 0. {
+                    self.font_size = font_size;
+                }
+            }
+            (AttributeId::FontSize, PredefValue(size)) => {
+                // the scaling factors of CSS
+                self.font_size = match size {
+                    ValueId::XxSmall => MEDIUM_FONT_SIZE * 3. / 5.,
+                    ValueId::XSmall => MEDIUM_FONT_SIZE * 3. / 4.,
+                    ValueId::Small => MEDIUM_FONT_SIZE * 8. / 9.,
+                    ValueId::Medium => MEDIUM_FONT_SIZE,
+                    ValueId::Large => MEDIUM_FONT_SIZE * 6. / 5.,
+                    ValueId::XLarge => MEDIUM_FONT_SIZE * 3. / 2.,
+                    ValueId::XxLarge => MEDIUM_FONT_SIZE * 2.,
+                    ValueId::Larger => self.font_size * 1.2,
+                    ValueId::Smaller => self.font_size / 1.2,
+                    _ => self.font_size,
+                }
+            }
+            (AttributeId::StrokeMiterlimit, svgparser::AttributeValue::Length(length)) => {
+                if length.unit == LengthUnit::None && length.num >= 1. {
+                    self.stroke_style.miter_limit = length.num as f32;
+                }
+            }
+            (AttributeId::Opacity, Number(num)) => self.opacity = num as f32,
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Element {
+    Svg(SvgRootMachine),
+    Line(LineMachine),
+    Polygon(PolygonMachine),
+    Polyline(PolygonMachine),
+    Rect(RectMachine),
+    Circle(EllipseMachine),
+    Ellipse(EllipseMachine),
+    Path(PathMachine),
+    LinearGradient(GradientMachine),
+    RadialGradient(GradientMachine),
+    Stop(StopMachine),
+    Use(UseMachine),
+    /// Only the view box and `preserveAspectRatio` of a `<symbol>` are used.
+    Symbol(SvgRootMachine),
+    ClipPath(ClipPathMachine),
+    Mask(MaskMachine),
+}
+
+impl Element {
+    fn svg_attribute(&mut self, id: AttributeId, val: AttributeValue) {
+        match *self {
+            Element::Svg(ref mut svg_machine) => svg_machine.attribute(id, val),
+            Element::Line(ref mut line_machine) => line_machine.attribute(id, val),
+            Element::Polygon(ref mut polygon_machine) |
+            Element::Polyline(ref mut polygon_machine) => polygon_machine.attribute(id, val),
+            Element::Rect(ref mut rect_machine) => rect_machine.attribute(id, val),
+            Element::Circle(ref mut ellipse_machine) |
+            Element::Ellipse(ref mut ellipse_machine) => ellipse_machine.attribute(id, val),
+            Element::Path(ref mut path_machine) => path_machine.attribute(id, val),
+            Element::LinearGradient(ref mut gradient_machine) |
+            Element::RadialGradient(ref mut gradient_machine) => {
+                gradient_machine.attribute(id, val)
+            }
+            Element::Stop(ref mut stop_machine) => stop_machine.attribute(id, val),
+            Element::Use(ref mut use_machine) => use_machine.attribute(id, val),
+            Element::Symbol(ref mut symbol_machine) => symbol_machine.attribute(id, val),
+            Element::ClipPath(ref mut clip_path_machine) => clip_path_machine.attribute(id, val),
+            Element::Mask(ref mut mask_machine) => mask_machine.attribute(id, val),
+        }
+    }
+
+    /// Whether the machine takes percentages as `AttributeValue::Percentage` instead of lengths
+    /// resolved against the viewport.
+    fn keeps_percentages(&self) -> bool {
+        match *self {
+            Element::Svg(_) |
+            Element::LinearGradient(_) |
+            Element::RadialGradient(_) |
+            Element::Stop(_) |
+            Element::Mask(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Returns the declarations of a `style` attribute, separating the properties unknown to
+/// svgparser.
+fn parse_style<'a>(
+    style: TextFrame<'a>,
+) -> (Vec<(AttributeId, TextFrame<'a>)>, Vec<(&'a str, &'a str)>) {
+    let mut declarations = Vec::new();
+    let mut unknown = Vec::new();
+    let mut tokenizer = style::Tokenizer::from_frame(style);
+    loop {
+        match tokenizer.parse_next() {
+            Ok(style::Token::SvgAttribute(AttributeId::Style, _)) => {}
+            Ok(style::Token::SvgAttribute(id, val)) => declarations.push((id, val)),
+            Ok(style::Token::XmlAttribute(name, val)) => unknown.push((name, val)),
+            Ok(style::Token::EntityRef(_)) => {}
+            Ok(style::Token::EndOfStream) | Err(_) => break,
+        }
+    }
+    (declarations, unknown)
+}
+
+/// Parses the value of a `transform` attribute into a single transformation.
+///
+/// The list is composed such that the rightmost transformation is applied first. Parsing stops at
+/// the first error, keeping the transformations read so far.
+fn parse_transform(val: TextFrame) -> Transform {
+    let mut result = Transform::identity();
+    let mut tokenizer = transform::Tokenizer::from_frame(val);
+    loop {
+        let next = match tokenizer.parse_next() {
+            Ok(transform::Token::EndOfStream) | Err(_) => break,
+            Ok(transform::Token::Matrix { a, b, c, d, e, f }) => {
+                Transform::new(a as f32, b as f32, c as f32, d as f32, e as f32, f as f32)
+            }
+            Ok(transform::Token::Translate { tx, ty }) => {
+                Transform::translate(tx as f32, ty as f32)
+            }
+            Ok(transform::Token::Scale { sx, sy }) => Transform::scale(sx as f32, sy as f32),
+            Ok(transform::Token::Rotate { angle }) => {
+                Transform::rotate((angle as f32).to_radians())
+            }
+            Ok(transform::Token::SkewX { angle }) => Transform::skew_x((angle as f32).to_radians()),
+            Ok(transform::Token::SkewY { angle }) => Transform::skew_y((angle as f32).to_radians()),
+        };
+        result = result * next;
+    }
+    result
+}
+
+/// Parses an SVG document, where `default_size` is the size of the image in pixels if the
+/// document specifies neither its size nor a view box. Percentages in the size of the document
+/// refer to `default_size`.
+pub fn parse_str(
+    svg: &str,
+    dpi: f32,
+    default_size: (f32, f32),
+) -> Result<VectorGraphic, SvgError> {
+    let mut parser = Parser::default();
+    parser.dpi = dpi;
+    parser.default_size = default_size;
+    parser.stack.push(ParserState::default());
+
+    // references may point to elements further down, so the document is read before it is
+    // processed
+    parser.read(svg)?;
+    let len = parser.events.len();
+    parser.process(0, len)?;
+
+    parser.resolve_paint_servers();
+    Ok(parser.result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use geometry::{CubicBezier, Line};
+
+    const EPS: f32 = 1.0e-4;
+
+    #[test]
+    fn test_path_data() {
+        // relative commands and an open subpath, which is closed for filling
+        let subpaths = parse_path_data("m 10 20 h 5 v 5 l -5 0");
+        assert_eq!(subpaths.len(), 1);
+        assert!(!subpaths[0].closed);
+        let segments = subpaths[0].clone().into_closed_segments();
+        let expected = [
+            Line::new(Point::new(10., 20.), Point::new(15., 20.)),
+            Line::new(Point::new(15., 20.), Point::new(15., 25.)),
+            Line::new(Point::new(15., 25.), Point::new(10., 25.)),
+            Line::new(Point::new(10., 25.), Point::new(10., 20.)),
+        ];
+        assert_eq!(segments.len(), expected.len());
+        for (segment, &line) in segments.iter().zip(expected.iter()) {
+            assert_eq!(*segment, Segment::Line(line));
+        }
+
+        // the smooth curve reflects the second control point of the previous curve
+        let subpaths = parse_path_data("M0 0 C 1 1 2 1 3 0 S 5 -1 6 0 Z");
+        assert!(subpaths[0].closed);
+        let segments = &subpaths[0].segments;
+        assert_eq!(
+            segments[1],
+            Segment::Cubic(CubicBezier::new(
+                Point::new(3., 0.),
+                Point::new(4., -1.),
+                Point::new(5., -1.),
+                Point::new(6., 0.),
+            ))
+        );
+        assert_eq!(segments.len(), 3);
+    }
+
+    #[test]
+    fn test_path_arc() {
+        // half of a circle with radius 1 around (1, 0), bulging towards positive y
+        let segments = &parse_path_data("M 0 0 A 1 1 0 0 0 2 0")[0].segments;
+        assert_eq!(segments.len(), 2);
+        assert!((segments[0].end() - Point::new(1., 1.)).norm() < EPS);
+        assert!((segments[1].end() - Point::new(2., 0.)).norm() < EPS);
+        if let Segment::Cubic(curve) = segments[0] {
+            let expected = Point::new(1. - 0.5f32.sqrt(), 0.5f32.sqrt());
+            assert!((curve.eval(0.5) - expected).norm() < 1e-3);
+        } else {
+            panic!("arcs should be converted into cubic curves");
+        }
+    }
+
+    #[test]
+    fn test_basic_shapes() {
+        let svg = r#"<svg xmlns="http://www.w3.org/2000/svg">
+            <rect x="1" y="2" width="10" height="8" rx="3"/>
+            <polygon points="0,0 1,0 1,1"/>
+            <circle cx="5" cy="5" r="2"/>
+        </svg>"#;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        assert_eq!(graphic.paths.len(), 3);
+
+        // `ry` defaults to `rx`
+        let rect = &graphic.paths[0].segments;
+        assert_eq!(rect.len(), 8);
+        assert_eq!(rect[0].start(), Point::new(4., 2.));
+        assert!((rect[1].end() - Point::new(11., 5.)).norm() < EPS);
+
+        // polygons are closed
+        let polygon = &graphic.paths[1].segments;
+        assert_eq!(polygon.len(), 3);
+        assert_eq!(polygon[2], Segment::Line(Line::new(Point::new(1., 1.), Point::new(0., 0.))));
+
+        let circle = &graphic.paths[2].segments;
+        assert_eq!(circle.len(), 4);
+        assert!((circle[3].end() - Point::new(7., 5.)).norm() < EPS);
+    }
+
+    #[test]
+    fn test_nested_transforms() {
+        let svg = r#"<svg width="100" height="100">
+            <g transform="translate(10 20)">
+                <foo:bar><baz/></foo:bar>
+                <g transform="scale(2) rotate(90)">
+                    <polygon points="0 0 1 0 1 1"/>
+                </g>
+                <polygon points="0 0 1 0 1 1" transform="matrix(1 0 0 1 5 0) skewX(45)"/>
+            </g>
+            <polygon points="0 0 1 0 1 1"/>
+        </svg>"#;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        assert_eq!(graphic.paths.len(), 3);
+
+        // (1, 1) is rotated to (-1, 1), scaled to (-2, 2) and then translated
+        let rotated = &graphic.paths[0].segments;
+        assert!((rotated[0].end() - Point::new(10., 22.)).norm() < EPS);
+        assert!((rotated[1].end() - Point::new(8., 22.)).norm() < EPS);
+
+        let skewed = &graphic.paths[1].segments;
+        assert!((skewed[1].end() - Point::new(17., 21.)).norm() < EPS);
+
+        // the transformations do not leak out of the group
+        let plain = &graphic.paths[2].segments;
+        assert_eq!(plain[1].end(), Point::new(1., 1.));
+    }
+
+    #[test]
+    fn test_use() {
+        let svg = r##"<svg width="100" height="100">
+            <use xlink:href="#triangle" x="10" y="20" transform="scale(2)" fill="red"/>
+            <defs>
+                <polygon id="triangle" points="0 0 1 0 1 1"/>
+                <g id="loop">
+                    <use xlink:href="#loop"/>
+                    <use xlink:href="#triangle"/>
+                </g>
+            </defs>
+            <use xlink:href="#loop"/>
+            <symbol id="icon" viewBox="0 0 10 10">
+                <rect width="10" height="10"/>
+            </symbol>
+            <use xlink:href="#icon" x="50" width="20" height="20"/>
+            <use xlink:href="#missing"/>
+        </svg>"##;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        assert_eq!(graphic.paths.len(), 3);
+
+        // the offset is applied after the transformation of the use element
+        let forward = &graphic.paths[0];
+        assert!((forward.segments[1].end() - Point::new(22., 42.)).norm() < EPS);
+        match forward.paint {
+            Paint::Color(color) => assert_eq!((color.red, color.green), (1., 0.)),
+            _ => panic!("expected a color"),
+        }
+
+        // the cyclic reference is skipped, but the rest of the group is instanced
+        let cyclic = &graphic.paths[1];
+        assert!((cyclic.segments[1].end() - Point::new(1., 1.)).norm() < EPS);
+
+        // the view box of the symbol is fitted into the size of the use element
+        let symbol = &graphic.paths[2];
+        let bbox = rasterizer::bounding_box(&symbol.segments);
+        assert!((bbox.origin - Point::new(50., 0.)).norm() < EPS);
+        assert!((bbox.size.width - 20.).abs() < EPS && (bbox.size.height - 20.).abs() < EPS);
+
+        // references to open ancestors are skipped, also through other references
+        let svg = r##"<svg width="100" height="100">
+            <g id="outer">
+                <use xlink:href="#outer"/>
+                <use xlink:href="#inner"/>
+                <rect width="10" height="10"/>
+            </g>
+            <defs>
+                <g id="inner">
+                    <use xlink:href="#outer"/>
+                    <rect width="20" height="20"/>
+                </g>
+            </defs>
+        </svg>"##;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        let widths = graphic
+            .paths
+            .iter()
+            .map(|path| rasterizer::bounding_box(&path.segments).size.width)
+            .collect::<Vec<_>>();
+        assert_eq!(widths, vec![20., 10.]);
+
+        // every level instances the one below ten times
+        let mut svg = r#"<svg><polygon id="level0" points="0 0 1 0 1 1"/>"#.to_string();
+        for level in 1..16 {
+            svg += &format!(r#"<g id="level{}">"#, level);
+            for _ in 0..10 {
+                svg += &format!(r##"<use xlink:href="#level{}"/>"##, level - 1);
+            }
+            svg += "</g>";
+        }
+        svg += "</svg>";
+        let err = parse_str(&svg, 96., (800., 600.)).unwrap_err();
+        assert_eq!(err.element, Some("use".to_string()));
+        match err.kind {
+            SvgErrorKind::TooManyInstances => {}
+            _ => panic!("expected too many instances"),
+        }
+    }
+
+    #[test]
+    fn test_clip_paths() {
+        let svg = r##"<svg width="100" height="100">
+            <clipPath id="left" clipPathUnits="objectBoundingBox">
+                <rect width="0.5" height="1"/>
+            </clipPath>
+            <g clip-path="url(#left)" transform="translate(10 0)">
+                <rect width="20" height="10"/>
+                <rect x="20" width="20" height="10" clip-path="url(#ring)"/>
+            </g>
+            <clipPath id="ring" clip-rule="evenodd">
+                <path d="M 0 0 h 10 v 10 h -10 z m 2 2 h 6 v 6 h -6 z"/>
+            </clipPath>
+            <rect width="10" height="10" clip-path="url(#missing)"/>
+        </svg>"##;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        assert_eq!(graphic.paths.len(), 3);
+        assert_eq!(graphic.clip_paths.len(), 2);
+        assert_eq!(graphic.paths[0].clip_paths, [1]);
+        assert_eq!(graphic.paths[1].clip_paths, [0, 1]);
+        assert!(graphic.paths[2].clip_paths.is_empty());
+
+        // the clip path is placed in the user space of the clipped element
+        let (ref ring, clip_rule) = graphic.clip_paths[0].shapes[0];
+        assert_eq!(clip_rule, FillRule::EvenOdd);
+        assert_eq!(rasterizer::bounding_box(ring), Rect::new(10., 0., 10., 10.));
+
+        // the left half of the bounding box of the group
+        let (ref left, clip_rule) = graphic.clip_paths[1].shapes[0];
+        assert_eq!(clip_rule, FillRule::NonZero);
+        let bbox = rasterizer::bounding_box(left);
+        assert!((bbox.origin - Point::new(10., 0.)).norm() < EPS);
+        assert!((bbox.size.width - 20.).abs() < EPS && (bbox.size.height - 10.).abs() < EPS);
+    }
+
+    #[test]
+    fn test_masks() {
+        let svg = r##"<svg width="100" height="100">
+            <polygon points="0 0 1 0 1 1" fill="url(#missing)"/>
+            <g mask="url(#fade)" transform="translate(10 0)">
+                <rect width="20" height="10"/>
+                <rect width="20" height="10" mask="url(#solid)"/>
+            </g>
+            <mask id="fade" maskContentUnits="objectBoundingBox">
+                <rect width="1" height="1" fill="white"/>
+            </mask>
+            <mask id="solid" mask-type="alpha" maskUnits="userSpaceOnUse" x="5" width="50%">
+                <rect width="10" height="10" mask="url(#solid)"/>
+            </mask>
+            <rect width="10" height="10" mask="url(#fade)" visibility="hidden" fill="none"/>
+        </svg>"##;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        // the two rects, the content of `solid` and the content of `fade`
+        assert_eq!(graphic.paths.len(), 4);
+        assert_eq!(graphic.masks.len(), 2);
+        assert_eq!((graphic.masks[0].start, graphic.masks[0].end), (2, 3));
+        assert_eq!((graphic.masks[1].start, graphic.masks[1].end), (3, 4));
+        assert_eq!(graphic.groups.len(), 2);
+        assert_eq!((graphic.groups[0].start, graphic.groups[0].end), (1, 2));
+        assert_eq!((graphic.groups[1].start, graphic.groups[1].end), (0, 3));
+        assert_eq!((graphic.groups[0].mask, graphic.groups[1].mask), (Some(0), Some(1)));
+
+        let solid = &graphic.masks[0];
+        assert!(!solid.luminance);
+        let bbox = rasterizer::bounding_box(&solid.region);
+        assert!((bbox.origin - Point::new(15., -10.)).norm() < EPS);
+        assert!((bbox.size.width - 50.).abs() < EPS && (bbox.size.height - 120.).abs() < EPS);
+
+        // the region extends beyond the bounding box of the group by 10%, while the content is
+        // relative to the bounding box
+        let fade = &graphic.masks[1];
+        assert!(fade.luminance);
+        let bbox = rasterizer::bounding_box(&fade.region);
+        assert!((bbox.origin - Point::new(8., -1.)).norm() < EPS);
+        assert!((bbox.size.width - 24.).abs() < EPS && (bbox.size.height - 12.).abs() < EPS);
+        let content = rasterizer::bounding_box(&graphic.paths[3].segments);
+        assert!((content.origin - Point::new(10., 0.)).norm() < EPS);
+        assert!((content.size.width - 20.).abs() < EPS);
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        let svg = r#"<svg width="10" height="10">
+            <rect width="5" height="5" mix-blend-mode="multiply"/>
+            <g mix-blend-mode="screen" style="mix-blend-mode: color-burn" opacity="0.5">
+                <rect width="5" height="5" mix-blend-mode="unknown"/>
+            </g>
+            <rect width="5" height="5" mix-blend-mode="normal"/>
+        </svg>"#;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        assert_eq!(graphic.paths.len(), 3);
+        let groups = graphic
+            .groups
+            .iter()
+            .map(|group| (group.start, group.end, group.blend_mode, group.opacity))
+            .collect::<Vec<_>>();
+        assert_eq!(groups, [(0, 1, BlendMode::Multiply, 1.), (1, 2, BlendMode::ColorBurn, 0.5)]);
+    }
+
+    #[test]
+    fn test_view_box() {
+        let view_box = Rect::new(-10., 0., 20., 10.);
+        let apply = |text, point| {
+            PreserveAspectRatio::parse(text).view_box_transform(view_box, (40., 40.)).apply(point)
+        };
+        // meet scales by 2 and centers vertically
+        assert_eq!(apply("xMidYMid", Point::new(-10., 0.)), Point::new(0., 10.));
+        assert_eq!(apply("defer xMaxYMax meet", Point::new(-10., 0.)), Point::new(0., 20.));
+        // slice scales by 4 and cuts off horizontally
+        assert_eq!(apply("xMidYMin slice", Point::new(-10., 0.)), Point::new(-20., 0.));
+        assert_eq!(apply("xMaxYMin slice", Point::new(10., 10.)), Point::new(40., 40.));
+        assert_eq!(apply("none", Point::new(10., 10.)), Point::new(40., 40.));
+        assert_eq!(apply("none", Point::new(0., 5.)), Point::new(20., 20.));
+        assert_eq!(PreserveAspectRatio::parse("xMidYMax bogus"), PreserveAspectRatio::default());
+
+        let svg = r#"<svg width="200" height="100" viewBox="0 0 10 10"
+                         preserveAspectRatio="xMinYMid meet">
+            <polygon points="0 0 10 0 10 10"/>
+        </svg>"#;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        assert_eq!(graphic.size, Some((200., 100.)));
+        assert_eq!(graphic.transform.apply(Point::new(10., 10.)), Point::new(100., 100.));
+
+        let graphic = parse_str(r#"<svg viewBox="5 5 30 20"></svg>"#, 96., (800., 600.)).unwrap();
+        assert_eq!(graphic.size, Some((30., 20.)));
+        assert_eq!(graphic.transform.apply(Point::new(5., 5.)), Point::origin());
+
+        // without a view box or size percentages refer to the default size
+        let svg = r#"<svg><rect width="50%" height="25%"/></svg>"#;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        assert_eq!(graphic.size, None);
+        assert_eq!(graphic.paths[0].segments[1].end(), Point::new(400., 150.));
+        let graphic = parse_str(r#"<svg width="50%" height="10"></svg>"#, 96., (800., 600.));
+        assert_eq!(graphic.unwrap().size, Some((400., 10.)));
+        // and so do percentages in the size of a root with a view box
+        let svg = r#"<svg width="100%" viewBox="0 0 30 20"></svg>"#;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        assert_eq!(graphic.size, Some((800., 20.)));
+    }
+
+    #[test]
+    fn test_fill_rule() {
+        let svg = r#"<svg width="10" height="10">
+            <g fill-rule="evenodd">
+                <polygon points="0 0 1 0 1 1"/>
+                <polygon points="0 0 1 0 1 1" fill-rule="nonzero"/>
+                <polygon points="0 0 1 0 1 1" fill-rule="inherit"/>
+            </g>
+            <polygon points="0 0 1 0 1 1"/>
+        </svg>"#;
+        let rules = parse_str(svg, 96., (800., 600.))
+            .unwrap()
+            .paths
+            .iter()
+            .map(|path| path.fill_rule)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rules,
+            [FillRule::EvenOdd, FillRule::NonZero, FillRule::EvenOdd, FillRule::NonZero]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let svg = "<svg>\n  <polygon fill=\"#zz\" points=\"0 0 1 x\"/>\n</svg>";
+        let err = parse_str(svg, 96., (800., 600.)).unwrap_err();
+        assert_eq!(err.position, Some((2, 37)));
+        assert_eq!(err.element, Some("polygon".to_string()));
+        match err.kind {
+            SvgErrorKind::InvalidAttribute(ref name, _) => assert_eq!(name, "points"),
+            _ => panic!("expected an invalid attribute"),
+        }
+
+        let svg = "<svg>\n<g>\n<rect width=\"1\" height=\"x\"/>\n</g>\n</svg>";
+        let err = parse_str(svg, 96., (800., 600.)).unwrap_err();
+        assert_eq!(err.position, Some((3, 25)));
+        assert_eq!(err.element, Some("rect".to_string()));
+
+        let err = parse_str("<svg>\n<rect width=1/>\n</svg>", 96., (800., 600.)).unwrap_err();
+        assert_eq!(err.position, Some((2, 13)));
+        match err.kind {
+            SvgErrorKind::Syntax(_) => {}
+            _ => panic!("expected a syntax error"),
+        }
+    }
+
+    #[test]
+    fn test_paint() {
+        let svg = r##"<svg width="10" height="10">
+            <g fill="#ff0000" color="lime" opacity="0.5">
+                <polygon points="0 0 1 0 1 1" fill-opacity="0.5"/>
+                <polygon points="0 0 1 0 1 1" fill="currentColor"/>
+                <polygon points="0 0 1 0 1 1" fill="none"/>
+                <g stroke="blue">
+                    <line x1="0" y1="0" x2="1" y2="0" stroke-opacity="0.25"/>
+                </g>
+            </g>
+            <polygon points="0 0 1 0 1 1" fill="url(#missing) white"/>
+            <line x1="0" y1="0" x2="1" y2="0"/>
+        </svg>"##;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        let colors = graphic
+            .paths
+            .iter()
+            .map(|path| match path.paint {
+                Paint::Color(color) => color,
+                _ => panic!("expected a color"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(colors.len(), 4);
+        assert_eq!(colors[0], Rgba::new(1., 0., 0., 0.5));
+        assert_eq!(colors[1], Rgba::new(0., 1., 0., 1.));
+        assert_eq!(colors[2], Rgba::new(0., 0., 1., 0.25));
+        assert_eq!(colors[3], Rgba::new(1., 1., 1., 1.));
+        // the opacity of the group applies to its paths as a whole
+        assert_eq!(graphic.groups.len(), 1);
+        let group = &graphic.groups[0];
+        assert_eq!((group.start, group.end, group.opacity, group.mask), (0, 3, 0.5, None));
+    }
+
+    #[test]
+    fn test_style() {
+        let svg = r#"<svg width="10" height="10">
+            <g style="fill: #0000ff; stroke:red ;" fill="lime" opacity="0.5">
+                <polygon points="0 0 1 0 1 1" style="fill-opacity:0.5;stroke-width :3"
+                         fill-opacity="0.25" stroke-width="2"/>
+                <polygon points="0 0 1 0 1 1" fill="black" style="fill:inherit; stroke: none"/>
+                <polygon points="0 0 1 0 1 1" opacity="0.5" style="opacity:0.5; stroke:none"/>
+            </g>
+            <rect width="1" height="1" fill="red" style="stroke: blue; opacity: 0.25"/>
+        </svg>"#;
+        let graphic = parse_str(svg, 96., (800., 600.)).unwrap();
+        let paths = &graphic.paths;
+        let colors = paths
+            .iter()
+            .map(|path| match path.paint {
+                Paint::Color(color) => color,
+                _ => panic!("expected a color"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(colors.len(), 6);
+        assert_eq!(colors[0], Rgba::new(0., 0., 1., 0.5));
+        assert_eq!(colors[1], Rgba::new(1., 0., 0., 1.));
+        assert_eq!(colors[2], Rgba::new(0., 0., 1., 1.));
+        // the opacity of a shape with a single path is applied to its paint
+        assert_eq!(colors[3], Rgba::new(0., 0., 1., 0.5));
+        // but the fill and the stroke of a shape are composited together
+        assert_eq!(colors[4], Rgba::new(1., 0., 0., 1.));
+        assert_eq!(graphic.groups.len(), 2);
+        assert_eq!((graphic.groups[0].start, graphic.groups[0].end), (0, 4));
+        assert_eq!((graphic.groups[1].start, graphic.groups[1].end), (4, 6));
+        assert_eq!(graphic.groups[1].opacity, 0.25);
+        // the stroke width of the style is used
+        let bbox = rasterizer::bounding_box(&paths[1].segments);
+        assert!((bbox.origin.y + 1.5).abs() < EPS);
+    }
+
+    #[test]
+    fn test_units() {
+        let svg = r#"<svg width="100%" height="50%" viewBox="0 0 300 400">
+            <rect x="1in" y="2.54cm" width="25.4mm" height="72pt" stroke="black"
+                  stroke-width="1pc"/>
+            <rect x="10%" y="25%" width="1em" height="2ex" font-size="10px" stroke="black"
+                  stroke-width="1%"/>
+            <g font-size="20">
+                <circle r="50%" font-size="50%" stroke="black" stroke-width="1em"/>
+            </g>
+        </svg>"#;
+        let graphic = parse_str(svg, 100., (800., 600.)).unwrap();
+        // percentages of the root refer to the default size, not to the view box
+        assert_eq!(graphic.size, Some((800., 300.)));
+        let bbox = |index: usize| rasterizer::bounding_box(&graphic.paths[index].segments);
+
+        let rect = bbox(0);
+        assert!((rect.origin.x - 100.).abs() < EPS && (rect.origin.y - 100.).abs() < EPS);
+        assert!((rect.size.width - 100.).abs() < EPS && (rect.size.height - 100.).abs() < EPS);
+        assert!((bbox(1).size.width - 100. - 100. / 6.).abs() < EPS);
+
+        let rect = bbox(2);
+        assert!((rect.origin.x - 30.).abs() < EPS && (rect.origin.y - 100.).abs() < EPS);
+        assert!((rect.size.width - 10.).abs() < EPS && (rect.size.height - 10.).abs() < EPS);
+        // the stroke width refers to the normalized diagonal of the view box
+        let width = 0.01 * (300f32 * 300. + 400. * 400.).sqrt() / 2f32.sqrt();
+        assert!((bbox(3).size.width - 10. - width).abs() < 1.0e-3);
+
+        let circle = bbox(4);
+        assert!((circle.size.width - (300f32 * 300. + 400. * 400.).sqrt() / 2f32.sqrt()).abs() <
+                    1.0e-2);
+        // `em` in an element refers to its own font size
+        assert!((bbox(5).size.width - circle.size.width - 10.).abs() < 1.0e-2);
+    }
+
+    #[test]
+    fn test_stroke_properties() {
+        let svg = r#"<svg width="20" height="20">
+            <g stroke="black" stroke-width="4" stroke-linecap="square" stroke-miterlimit="1">
+                <line x1="0" y1="0" x2="10" y2="0"/>
+                <polyline points="0 0 10 0 10 10" fill="none" stroke-linejoin="miter"/>
+            </g>
+        </svg>"#;
+        let paths = parse_str(svg, 96., (800., 600.)).unwrap().paths;
+        assert_eq!(paths.len(), 2);
+        assert_eq!(
+            rasterizer::bounding_box(&paths[0].segments),
+            Rect::new(-2., -2., 14., 4.)
+        );
+        // the miter limit turns the corner into a bevel
+        let corner = Point::new(12., -2.);
+        assert!(paths[1].segments.iter().all(|segment| segment.end() != corner));
+    }
+
+    #[test]
+    fn test_gradients() {
+        let svg = r##"<svg width="100" height="50">
+            <rect x="10" y="10" width="20" height="10" fill="url(#derived)"/>
+            <rect x="0" y="0" width="10" height="10" fill="url(#radial)" fill-opacity="0.5"/>
+            <rect x="0" y="0" width="10" height="10" fill="url(#empty) red"/>
+            <defs>
+                <linearGradient id="base" x2="0" y2="1">
+                    <stop offset="0" stop-color="red"/>
+                    <stop offset="50%" stop-color="blue" stop-opacity="0.5"/>
+                </linearGradient>
+                <linearGradient id="derived" xlink:href="#base" spreadMethod="reflect"/>
+                <radialGradient id="radial" gradientUnits="userSpaceOnUse" r="50%" fx="200">
+                    <stop offset="0.5" stop-color="lime"/>
+                    <stop offset="0.2" stop-color="black"/>
+                </radialGradient>
+                <linearGradient id="empty"/>
+            </defs>
+        </svg>"##;
+        let paths = parse_str(svg, 96., (800., 600.)).unwrap().paths;
+        assert_eq!(paths.len(), 2);
+
+        let linear = match paths[0].paint {
+            Paint::LinearGradient(ref linear) => linear,
+            _ => panic!("expected a linear gradient"),
+        };
+        assert_eq!(linear.gradient.spread, SpreadMethod::Reflect);
+        assert_eq!(linear.gradient.stops.len(), 2);
+        assert!((linear.gradient.stops[1].0 - 0.5).abs() < EPS);
+        assert!((linear.gradient.stops[1].1.alpha - 0.5).abs() < EPS);
+        // the gradient runs from the top to the bottom of the bounding box
+        let (direction, offset) = linear.parameter().unwrap();
+        let t = |x: f32, y: f32| direction.x * x + direction.y * y + offset;
+        assert!(t(15., 10.).abs() < EPS);
+        assert!((t(25., 20.) - 1.).abs() < EPS);
+
+        let radial = match paths[1].paint {
+            Paint::RadialGradient(ref radial) => radial,
+            _ => panic!("expected a radial gradient"),
+        };
+        // the radius is relative to the normalized diagonal of the viewport
+        let radius = (100f32 * 100. + 50. * 50.).sqrt() / 2f32.sqrt() * 0.5;
+        assert!((radial.radius - radius).abs() < EPS);
+        assert!((radial.center.x - 50.).abs() < EPS && (radial.center.y - 25.).abs() < EPS);
+        assert!(((radial.focal - radial.center).norm() - 0.999 * radius).abs() < 1.0e-3);
+        // offsets are made increasing and the opacity of the path is applied to the stops
+        assert!((radial.gradient.stops[1].0 - 0.5).abs() < EPS);
+        assert!((radial.gradient.stops[0].1.alpha - 0.5).abs() < EPS);
+    }
+}